@@ -0,0 +1,84 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use packet_13_22::arena::PacketArena;
+use packet_13_22::datum::PacketDatum;
+
+mod common;
+
+// PacketDatum (an Rc<RefCell<..>> per list and integer) vs PacketArena (two flat Vecs):
+// - memory per packet, measured with a counting allocator
+// - time to sort every packet
+
+const PACKETS: usize = 100_000;
+
+#[global_allocator]
+static GLOBAL: common::CountingAllocator = common::CountingAllocator;
+
+fn parse_packet_datums(input: &str) -> Vec<PacketDatum> {
+    input.lines().map(|line| line.parse().unwrap()).collect()
+}
+
+fn parse_arena(input: &str) -> PacketArena {
+    let mut arena = PacketArena::new();
+
+    for line in input.lines() {
+        arena.parse(line).unwrap();
+    }
+
+    arena
+}
+
+fn memory_per_packet(input: &str) {
+    let (packet_datums, packet_datum_bytes) = common::allocated_by(|| parse_packet_datums(input));
+    drop(packet_datums);
+
+    let (arena, arena_bytes) = common::allocated_by(|| parse_arena(input));
+    drop(arena);
+
+    println!(
+        "memory per packet: PacketDatum {} bytes, PacketArena {} bytes",
+        packet_datum_bytes / PACKETS,
+        arena_bytes / PACKETS
+    );
+}
+
+fn sort(c: &mut Criterion) {
+    let input = common::generate_input(PACKETS, 5);
+    memory_per_packet(&input);
+
+    let mut group = c.benchmark_group("arena");
+    group.sample_size(10);
+
+    let packet_datums = parse_packet_datums(&input);
+    group.bench_function("sort_packet_datum", |b| {
+        b.iter_batched(
+            || packet_datums.clone(),
+            |mut packets| {
+                packets.sort();
+                packets
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    let arena = parse_arena(&input);
+    group.bench_function("sort_arena", |b| {
+        b.iter_batched(
+            || arena.packets().to_vec(),
+            |mut packets| {
+                arena.sort(&mut packets);
+                packets
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("parse_packet_datum", |b| {
+        b.iter(|| parse_packet_datums(&input))
+    });
+    group.bench_function("parse_arena", |b| b.iter(|| parse_arena(&input)));
+
+    group.finish();
+}
+
+criterion_group!(benches, sort);
+criterion_main!(benches);
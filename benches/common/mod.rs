@@ -0,0 +1,90 @@
+// Generated packet files and the allocation counter shared by the benchmarks.
+// Everything is seeded, so every run benchmarks exactly the same packets.
+//
+// Each bench uses a different part of this module.
+#![allow(dead_code)]
+
+use packet_13_22::generate::{Distribution, PacketGenerator};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// One random packet per line, lists nested up to max_depth, integers 0..=10.
+pub fn generate_input(packets: usize, max_depth: usize) -> String {
+    let generator =
+        PacketGenerator::new(0x2545f4914f6cdd1d).depth(Distribution::Uniform(1..=max_depth));
+
+    packet_lines(generator, packets)
+}
+
+/// Puzzle sized packets: depth 1..=4, lists of 0..=5 items.
+pub fn day_thirteen_input(packets: usize) -> String {
+    packet_lines(PacketGenerator::new(13), packets)
+}
+
+/// Flat lists of 5..=15 integers.
+pub fn shallow_input(packets: usize) -> String {
+    let generator = PacketGenerator::new(1)
+        .depth(Distribution::Constant(1))
+        .list_length(Distribution::Uniform(5..=15))
+        .integers(0..=100);
+
+    packet_lines(generator, packets)
+}
+
+/// 50 levels of nesting, lists of 1..=2 items.
+pub fn deep_input(packets: usize) -> String {
+    let generator = PacketGenerator::new(2)
+        .depth(Distribution::Constant(50))
+        .list_length(Distribution::Uniform(1..=2))
+        .list_probability(0.0);
+
+    packet_lines(generator, packets)
+}
+
+/// Lists of 100..=200 items, two levels deep.
+pub fn wide_input(packets: usize) -> String {
+    let generator = PacketGenerator::new(3)
+        .depth(Distribution::Constant(2))
+        .list_length(Distribution::Uniform(100..=200))
+        .list_probability(0.05)
+        .integers(0..=1000);
+
+    packet_lines(generator, packets)
+}
+
+fn packet_lines(mut generator: PacketGenerator, packets: usize) -> String {
+    let mut input = String::new();
+
+    for _ in 0..packets {
+        input.push_str(&generator.generate().to_string());
+        input.push('\n');
+    }
+
+    input
+}
+
+/// Counts the bytes currently allocated. A bench opts in with
+/// #[global_allocator] static GLOBAL: common::CountingAllocator = common::CountingAllocator;
+pub struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Runs f, and returns what it built with the bytes still allocated for it.
+pub fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let built = f();
+
+    (built, ALLOCATED.load(Ordering::Relaxed) - before)
+}
@@ -0,0 +1,125 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use packet_13_22::datum::PacketDatum;
+
+mod common;
+
+// The baseline suite for parsing and comparing PacketDatums:
+// - parse: throughput on shallow, deep and wide packets
+// - compare: part 1, every pair of a generated input compared once
+// - part_two: sorting a generated input with the two divider packets
+// - memory per packet for each input shape, printed before the benchmarks run
+//
+// To check a change for regressions:
+//     cargo bench --bench packets -- --save-baseline before
+//     (make the change)
+//     cargo bench --bench packets -- --baseline before
+
+// roughly 300 KB of text per shape
+const PACKETS: usize = 10_000;
+const DEEP_PACKETS: usize = 2_000;
+const WIDE_PACKETS: usize = 60;
+
+#[global_allocator]
+static GLOBAL: common::CountingAllocator = common::CountingAllocator;
+
+// (shape, packets, input)
+fn inputs() -> Vec<(&'static str, usize, String)> {
+    vec![
+        ("shallow", PACKETS, common::shallow_input(PACKETS)),
+        ("deep", DEEP_PACKETS, common::deep_input(DEEP_PACKETS)),
+        ("wide", WIDE_PACKETS, common::wide_input(WIDE_PACKETS)),
+    ]
+}
+
+fn parse_lines(input: &str) -> Vec<PacketDatum> {
+    input.lines().map(|line| line.parse().unwrap()).collect()
+}
+
+fn memory_per_packet() {
+    for (shape, packets, input) in inputs() {
+        let (packet_datums, bytes) = common::allocated_by(|| parse_lines(&input));
+        drop(packet_datums);
+
+        println!(
+            "memory per packet ({}): {} bytes for {} bytes of text",
+            shape,
+            bytes / packets,
+            input.len() / packets
+        );
+    }
+}
+
+fn parse(c: &mut Criterion) {
+    memory_per_packet();
+
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(20);
+
+    for (shape, _, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(shape), &input, |b, input| {
+            b.iter(|| parse_lines(input))
+        });
+    }
+
+    group.finish();
+}
+
+fn compare(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compare");
+
+    let mut inputs = inputs();
+    inputs.push(("day_thirteen", PACKETS, common::day_thirteen_input(PACKETS)));
+
+    for (shape, _, input) in inputs {
+        let packets = parse_lines(&input);
+
+        group.throughput(Throughput::Elements(packets.len() as u64 / 2));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape),
+            &packets,
+            |b, packets| {
+                b.iter(|| {
+                    packets
+                        .chunks(2)
+                        .enumerate()
+                        .filter(|(_, pair)| pair[0] < pair[1])
+                        .map(|(idx, _)| idx + 1)
+                        .sum::<usize>()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn part_two(c: &mut Criterion) {
+    let mut group = c.benchmark_group("part_two");
+    group.sample_size(20);
+
+    let mut packets = parse_lines(&common::day_thirteen_input(PACKETS));
+    let dividers: [PacketDatum; 2] = ["[[2]]".parse().unwrap(), "[[6]]".parse().unwrap()];
+    packets.extend(dividers.clone());
+
+    group.throughput(Throughput::Elements(packets.len() as u64));
+    group.bench_function("sort", |b| {
+        b.iter_batched(
+            || packets.clone(),
+            |mut packets| {
+                packets.sort();
+
+                dividers
+                    .iter()
+                    .map(|divider| packets.iter().position(|p| p == divider).unwrap() + 1)
+                    .product::<usize>()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse, compare, part_two);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use packet_13_22::input;
+use packet_13_22::parallel;
+
+mod common;
+
+// Sorting a generated packet file sequentially vs across 1, 4 and 16 threads.
+
+const PACKETS: usize = 100_000;
+
+fn sort(c: &mut Criterion) {
+    let input = common::generate_input(PACKETS, 5);
+    let mut group = c.benchmark_group("sort");
+    group.sample_size(10);
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let mut packets = input::read_packets(input.as_bytes()).unwrap();
+            packets.sort();
+            packets
+        })
+    });
+
+    // the packets stay text, validated and compared straight from it
+    for threads in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("parallel_lines", threads),
+            &threads,
+            |b, &threads| b.iter(|| parallel::sort_lines(&input, threads).unwrap()),
+        );
+    }
+
+    // parsed across threads into SyncPacketDatums
+    for threads in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("parallel_sync", threads),
+            &threads,
+            |b, &threads| b.iter(|| parallel::sort_sync_packets(&input, threads).unwrap()),
+        );
+    }
+
+    // the same, converted to PacketDatums at the end
+    for threads in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("parallel", threads),
+            &threads,
+            |b, &threads| b.iter(|| parallel::sort_packets(&input, threads).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, sort);
+criterion_main!(benches);
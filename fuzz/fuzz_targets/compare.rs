@@ -0,0 +1,43 @@
+#![no_main]
+
+// Two packets, one per line. Every comparison in the crate has to agree with PacketDatum::cmp,
+// and cmp itself has to be antisymmetric.
+
+use libfuzzer_sys::fuzz_target;
+use packet_13_22::arena::PacketArena;
+use packet_13_22::datum::{compare_str, PacketDatum, PacketRef, SyncPacketDatum};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Some((left, right)) = s.split_once('\n') else {
+        return;
+    };
+    let (Ok(left_datum), Ok(right_datum)) =
+        (left.parse::<PacketDatum>(), right.parse::<PacketDatum>())
+    else {
+        return;
+    };
+
+    let ordering = left_datum.cmp(&right_datum);
+    assert!(right_datum.cmp(&left_datum) == ordering.reverse());
+
+    let (left_sync, right_sync) = (
+        SyncPacketDatum::from(&left_datum),
+        SyncPacketDatum::from(&right_datum),
+    );
+    assert!(left_sync.cmp(&right_sync) == ordering);
+
+    let mut arena = PacketArena::new();
+    let (left_id, right_id) = (
+        arena.push_datum(&left_datum),
+        arena.push_datum(&right_datum),
+    );
+    assert!(arena.cmp(left_id, right_id) == ordering);
+
+    // the text comparisons read the same grammar as from_str
+    let (left_ref, right_ref) = (PacketRef::new(left).unwrap(), PacketRef::new(right).unwrap());
+    assert!(left_ref.cmp(&right_ref) == ordering);
+    assert!(compare_str(left, right).unwrap() == ordering);
+});
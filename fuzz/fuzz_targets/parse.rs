@@ -0,0 +1,54 @@
+#![no_main]
+
+// The parsers that read the packet grammar, on arbitrary text: none of them may panic.
+// (arbitrary::check_parsers_never_panic covers the rest under proptest.)
+//
+// from_str, SyncPacketDatum's from_str and validate_str read the same grammar:
+// they accept exactly the same text, and the two parsers build the same packet.
+// parse_recovering finds no errors exactly when the text is valid, and builds the same packet.
+// StreamParser, fed the text in two chunks, has to find the one packet exactly when it's valid.
+
+use libfuzzer_sys::fuzz_target;
+use packet_13_22::datum::{
+    parse_recovering, validate_str, PacketDatum, PacketRef, SyncPacketDatum,
+};
+use packet_13_22::stream::StreamParser;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let packet_datum = s.parse::<PacketDatum>();
+    let sync_packet_datum = s.parse::<SyncPacketDatum>();
+    let valid = validate_str(s).is_ok();
+
+    let recovered = parse_recovering(s);
+
+    let mut stream_parser = StreamParser::new()
+        .max_depth(usize::MAX)
+        .max_packet_size(usize::MAX);
+    let (first, second) = data.split_at(data.first().map_or(0, |b| *b as usize % (data.len() + 1)));
+    let streamed = stream_parser
+        .feed(first)
+        .and_then(|()| stream_parser.feed(second))
+        .and_then(|()| stream_parser.finish());
+    let streamed_packets: Vec<PacketDatum> =
+        std::iter::from_fn(|| stream_parser.next_packet()).collect();
+
+    assert!(packet_datum.as_ref().ok().map(SyncPacketDatum::from) == sync_packet_datum.ok());
+    assert!(packet_datum.is_ok() == valid);
+    assert!(recovered.is_ok() == valid);
+    assert!((streamed.is_ok() && streamed_packets.len() == 1) == valid);
+
+    if valid {
+        let packet_datum = packet_datum.expect("from_str rejected a valid packet");
+
+        let packet_ref = PacketRef::new(s).expect("PacketRef rejected a valid packet");
+        assert!(packet_ref.to_datum() == packet_datum);
+
+        assert!(streamed_packets[0] == packet_datum);
+
+        assert!(recovered.packet == Some(packet_datum));
+    }
+});
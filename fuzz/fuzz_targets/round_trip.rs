@@ -0,0 +1,26 @@
+#![no_main]
+
+// Anything that parses prints (one comma between items, no whitespace) as text
+// that parses back to the same packet.
+
+use libfuzzer_sys::fuzz_target;
+use packet_13_22::datum::{validate_str, PacketDatum, SyncPacketDatum};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(packet_datum) = s.parse::<PacketDatum>() else {
+        return;
+    };
+
+    let text = packet_datum.to_string();
+    assert!(validate_str(&text).is_ok());
+
+    let reparsed: PacketDatum = text.parse().expect("printed packet didn't parse");
+    assert!(reparsed == packet_datum);
+
+    let sync_packet_datum = SyncPacketDatum::from(&packet_datum);
+    assert!(sync_packet_datum.to_string() == text);
+    assert!(text.parse::<SyncPacketDatum>().unwrap() == sync_packet_datum);
+});
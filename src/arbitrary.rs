@@ -0,0 +1,449 @@
+use crate::arena::PacketArena;
+use crate::datum::{
+    compare_str, diagnose, parse_recovering, validate_str, Dialect, PacketComparator, PacketDatum,
+    PacketRef, SyncPacketDatum,
+};
+use crate::generate::PacketGenerator;
+use crate::stream::StreamParser;
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+// The strategy builds packets top down, one level at a time:
+// a list at depth d holds 0..=max_width items, each item is an integer,
+// or (while d < max_depth) with the same odds another list at depth d + 1.
+// With a strings pattern set, strings matching it are one more option with the same odds.
+// The root is always a list, so max_depth 1 means flat lists of atoms.
+//
+// Shrinking comes from proptest's Vec strategy: lists lose items and integers move
+// toward the start of the range, so failures shrink down to small packets.
+//
+// The check_ functions are the property suite itself. They return proptest's
+// TestCaseError, so they can be called with ? from any proptest! block,
+// including ones in other crates with their own packets or comparators.
+
+/// Arbitrary Module (cargo feature "proptest"):
+/// proptest strategies for packets, and a reusable property test suite.
+///
+/// ex:
+/// proptest! {
+///     fn my_comparator_is_a_total_order(a in any::<PacketDatum>(), b in any::<PacketDatum>(), c in any::<PacketDatum>()) {
+///         check_ord_laws(&MyComparator, &a, &b, &c)?;
+///     }
+/// }
+///
+/// Contains:
+/// - PacketParams: max depth, max width, integer range and strings of generated packets
+/// - Arbitrary for PacketDatum: any::<PacketDatum>() or any_with::<PacketDatum>(params)
+/// - packet_strings / packet_like_strings: valid packet text, and text that's nearly packet text
+/// - check_ord_laws, check_display_round_trip, check_parsers_never_panic
+#[derive(Clone, Debug)]
+pub struct PacketParams {
+    max_depth: usize,
+    max_width: usize,
+    integers: RangeInclusive<i32>,
+    // regex the strings match, None for packets without strings
+    strings: Option<String>,
+}
+
+impl Default for PacketParams {
+    /// Shaped like the puzzle input: up to 4 levels of lists, 5 items per list, integers 0..=10,
+    /// no strings.
+    fn default() -> Self {
+        PacketParams {
+            max_depth: 4,
+            max_width: 5,
+            integers: 0..=10,
+            strings: None,
+        }
+    }
+}
+
+impl PacketParams {
+    pub fn new() -> Self {
+        PacketParams::default()
+    }
+
+    /// Deepest list nesting, the root list counts as 1. 0 is treated as 1.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self
+    }
+
+    /// Most items in any one list.
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Range every generated integer comes from.
+    /// A small range makes equal integers, and so Equal comparisons, more likely.
+    pub fn integers(mut self, integers: RangeInclusive<i32>) -> Self {
+        self.integers = integers;
+        self
+    }
+
+    /// Adds string atoms matching the regex pattern, ex: "[ab]{0,2}".
+    /// A small alphabet makes equal strings, and so Equal comparisons, more likely.
+    ///
+    /// Panics when the strategy is built if pattern isn't a valid regex.
+    pub fn strings(mut self, pattern: &str) -> Self {
+        self.strings = Some(pattern.to_string());
+        self
+    }
+}
+
+impl Arbitrary for PacketDatum {
+    type Parameters = PacketParams;
+    type Strategy = BoxedStrategy<PacketDatum>;
+
+    fn arbitrary_with(params: PacketParams) -> Self::Strategy {
+        list_strategy(&params, 1)
+    }
+}
+
+fn list_strategy(params: &PacketParams, depth: usize) -> BoxedStrategy<PacketDatum> {
+    let integer = params.integers.clone().prop_map(PacketDatum::Integer);
+
+    let atom = match &params.strings {
+        Some(pattern) => {
+            let string = proptest::string::string_regex(pattern)
+                .expect("PacketParams::strings takes a valid regex")
+                .prop_map(PacketDatum::String);
+
+            prop_oneof![integer, string].boxed()
+        }
+        None => integer.boxed(),
+    };
+
+    let item = match (depth < params.max_depth, params.strings.is_some()) {
+        // strings are already half the atoms: keep a third of the items each
+        (true, true) => prop_oneof![2 => atom, 1 => list_strategy(params, depth + 1)].boxed(),
+        (true, false) => prop_oneof![atom, list_strategy(params, depth + 1)].boxed(),
+        (false, _) => atom,
+    };
+
+    prop::collection::vec(item, 0..=params.max_width)
+        .prop_map(|items| {
+            PacketDatum::List(
+                items
+                    .into_iter()
+                    .map(|item| Rc::new(RefCell::new(item)))
+                    .collect(),
+            )
+        })
+        .boxed()
+}
+
+/// Valid packet strings, as Display prints them.
+pub fn packet_strings(params: PacketParams) -> impl Strategy<Value = String> {
+    any_with::<PacketDatum>(params).prop_map(|packet_datum| packet_datum.to_string())
+}
+
+/// Short strings made of packet characters ([ ] , - digits, spaces, quotes, \ and a few
+/// letters for escapes) in any order.
+///
+/// Most aren't valid packets, but they get much further into a parser than random text does.
+pub fn packet_like_strings() -> impl Strategy<Value = String> {
+    "[\\[\\],\\- 0-9\"\\\\nu]{0,32}"
+}
+
+/// Checks comparator is a total order on these three packets:
+/// - reflexive: a is Equal to itself
+/// - antisymmetric: comparing b with a is the reverse of comparing a with b
+/// - transitive: a <= b and b <= c means a <= c
+/// - consistent with ==: structurally equal packets are Equal
+pub fn check_ord_laws<C: PacketComparator>(
+    comparator: &C,
+    a: &PacketDatum,
+    b: &PacketDatum,
+    c: &PacketDatum,
+) -> Result<(), TestCaseError> {
+    for packet_datum in [a, b, c] {
+        prop_assert_eq!(
+            comparator.compare(packet_datum, packet_datum),
+            Ordering::Equal
+        );
+    }
+
+    for (left, right) in [(a, b), (b, c), (a, c)] {
+        prop_assert_eq!(
+            comparator.compare(left, right),
+            comparator.compare(right, left).reverse()
+        );
+
+        if left == right {
+            prop_assert_eq!(comparator.compare(left, right), Ordering::Equal);
+        }
+    }
+
+    // every order of the three, so each one gets a turn in the middle
+    for (x, y, z) in [
+        (a, b, c),
+        (a, c, b),
+        (b, a, c),
+        (b, c, a),
+        (c, a, b),
+        (c, b, a),
+    ] {
+        if comparator.compare(x, y).is_le() && comparator.compare(y, z).is_le() {
+            prop_assert!(
+                comparator.compare(x, z).is_le(),
+                "{} <= {} <= {} but not {} <= {}",
+                x,
+                y,
+                z,
+                x,
+                z
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks printing a packet and parsing it back gives an equal packet,
+/// for both PacketDatum and SyncPacketDatum, and that what's printed is valid JSON.
+pub fn check_display_round_trip(packet_datum: &PacketDatum) -> Result<(), TestCaseError> {
+    let text = packet_datum.to_string();
+
+    let parsed: PacketDatum = text
+        .parse()
+        .map_err(|_| TestCaseError::fail(format!("{} didn't parse", text)))?;
+    prop_assert_eq!(&parsed, packet_datum);
+
+    let sync_packet_datum = SyncPacketDatum::from(packet_datum);
+    prop_assert_eq!(sync_packet_datum.to_string(), text.clone());
+    prop_assert_eq!(
+        text.parse::<SyncPacketDatum>().ok(),
+        Some(sync_packet_datum)
+    );
+
+    prop_assert_eq!(
+        PacketDatum::from_json_str(&text).ok(),
+        Some(packet_datum.clone())
+    );
+
+    Ok(())
+}
+
+/// Runs the crate's parsers on s: from_str, SyncPacketDatum's from_str, validate_str, compare_str,
+/// PacketRef::new, parse_recovering, diagnose, PacketArena::parse, StreamParser,
+/// PacketDatum::from_json_str, Dialect::parse (the three built in dialects)
+/// and PacketGenerator::template. A panic fails the test,
+/// malformed text just has to come back as an Err.
+///
+/// The ones that read the packet grammar have to agree on whether s is a packet, and on the packet.
+/// The others only have to agree where their grammars overlap with it, and anything that parses
+/// has to round trip through Display.
+/// PacketCodec (cargo feature "tokio") isn't run: it cuts frames and parses them with from_str.
+pub fn check_parsers_never_panic(s: &str) -> Result<(), TestCaseError> {
+    let valid = validate_str(s).is_ok();
+    let packet_datum = s.parse::<PacketDatum>().ok();
+
+    prop_assert_eq!(packet_datum.is_some(), valid);
+    prop_assert_eq!(
+        packet_datum.as_ref().map(SyncPacketDatum::from),
+        s.parse::<SyncPacketDatum>().ok()
+    );
+    prop_assert_eq!(compare_str(s, s).ok(), valid.then_some(Ordering::Equal));
+    prop_assert_eq!(
+        PacketRef::new(s)
+            .ok()
+            .map(|packet_ref| packet_ref.to_datum()),
+        packet_datum.clone()
+    );
+
+    let recovered = parse_recovering(s);
+    prop_assert_eq!(recovered.is_ok(), valid);
+    prop_assert_eq!(diagnose(s).is_none(), valid);
+
+    let mut arena = PacketArena::new();
+    let arena_packet = arena.parse(s).ok().map(|id| arena.to_datum(id));
+    prop_assert_eq!(arena_packet, packet_datum.clone());
+
+    // a stream can hold any number of packets, a valid packet is exactly one
+    let mut stream_parser = StreamParser::new()
+        .max_depth(usize::MAX)
+        .max_packet_size(usize::MAX);
+    let streamed = stream_parser
+        .feed(s.as_bytes())
+        .and_then(|()| stream_parser.finish());
+    if valid {
+        prop_assert!(streamed.is_ok());
+        prop_assert_eq!(stream_parser.next_packet(), packet_datum.clone());
+        prop_assert!(stream_parser.next_packet().is_none());
+    }
+
+    // JSON is stricter than the packet grammar
+    if let Ok(json_packet) = PacketDatum::from_json_str(s) {
+        prop_assert_eq!(Some(json_packet), packet_datum.clone());
+    }
+
+    for dialect in [Dialect::BRACKETS, Dialect::S_EXPRESSION, Dialect::BRACES] {
+        if let Ok(dialect_packet) = dialect.parse(s) {
+            check_display_round_trip(&dialect_packet)?;
+        }
+    }
+
+    // the brackets dialect is the packet grammar with exactly one comma between items
+    if let Ok(dialect_packet) = Dialect::BRACKETS.parse(s) {
+        prop_assert_eq!(Some(dialect_packet), packet_datum.clone());
+    }
+
+    // templates are the packet grammar plus _ and *
+    let template = PacketGenerator::new(0).template(s);
+    prop_assert!(template.is_ok() || !valid);
+
+    if let Some(packet_datum) = packet_datum {
+        check_display_round_trip(&packet_datum)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::{
+        ByDepth, BySum, DayThirteen, Dialect, LongerListFirst, NoPromotion, ReverseIntegers,
+        Separator, Shortlex,
+    };
+
+    fn shape(packet_datum: &PacketDatum) -> (usize, usize) {
+        match packet_datum {
+            PacketDatum::Integer(_) | PacketDatum::String(_) => (0, 0),
+            PacketDatum::List(l) => {
+                let (depth, width) = l
+                    .iter()
+                    .map(|p| shape(&p.borrow()))
+                    .fold((0, l.len()), |(d, w), (d2, w2)| (d.max(d2), w.max(w2)));
+
+                (depth + 1, width)
+            }
+        }
+    }
+
+    fn integers(packet_datum: &PacketDatum) -> Vec<i32> {
+        match packet_datum {
+            PacketDatum::Integer(i) => vec![*i],
+            PacketDatum::String(_) => vec![],
+            PacketDatum::List(l) => l.iter().flat_map(|p| integers(&p.borrow())).collect(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn params_bound_packets(
+            packet_datum in any_with::<PacketDatum>(
+                PacketParams::new().max_depth(2).max_width(3).integers(-5..=5)
+            )
+        ) {
+            let (depth, width) = shape(&packet_datum);
+
+            prop_assert!((1..=2).contains(&depth));
+            prop_assert!(width <= 3);
+            prop_assert!(integers(&packet_datum).iter().all(|i| (-5..=5).contains(i)));
+        }
+
+        #[test]
+        fn day_thirteen_is_a_total_order(
+            a in any::<PacketDatum>(),
+            b in any::<PacketDatum>(),
+            c in any::<PacketDatum>(),
+        ) {
+            check_ord_laws(&DayThirteen, &a, &b, &c)?;
+            check_ord_laws(&|left: &PacketDatum, right: &PacketDatum| left.cmp(right), &a, &b, &c)?;
+        }
+
+        #[test]
+        fn day_thirteen_is_a_total_order_with_strings(
+            a in any_with::<PacketDatum>(PacketParams::new().integers(0..=2).strings("[ab]{0,2}")),
+            b in any_with::<PacketDatum>(PacketParams::new().integers(0..=2).strings("[ab]{0,2}")),
+            c in any_with::<PacketDatum>(PacketParams::new().integers(0..=2).strings("[ab]{0,2}")),
+        ) {
+            check_ord_laws(&DayThirteen, &a, &b, &c)?;
+            check_ord_laws(&ReverseIntegers, &a, &b, &c)?;
+            check_ord_laws(&NoPromotion, &a, &b, &c)?;
+            check_ord_laws(&Shortlex, &a, &b, &c)?;
+        }
+
+        #[test]
+        // whatever the values, and also for the lists Equal to a string, like [["a"]]
+        fn strings_sort_after_integers(
+            a in any_with::<PacketDatum>(PacketParams::new().strings("[ab]{0,2}")),
+            i in any::<i32>(),
+            s in "[ab]{0,2}",
+        ) {
+            let integer = PacketDatum::Integer(i);
+            let string = PacketDatum::String(s);
+
+            prop_assert_eq!(integer.cmp(&string), Ordering::Less);
+
+            if a.cmp(&string) == Ordering::Equal {
+                prop_assert_eq!(a.cmp(&integer), Ordering::Greater);
+            }
+        }
+
+        #[test]
+        fn other_comparators_are_total_orders(
+            a in any::<PacketDatum>(),
+            b in any::<PacketDatum>(),
+            c in any::<PacketDatum>(),
+        ) {
+            check_ord_laws(&ReverseIntegers, &a, &b, &c)?;
+            check_ord_laws(&LongerListFirst, &a, &b, &c)?;
+            check_ord_laws(&Shortlex, &a, &b, &c)?;
+            check_ord_laws(&NoPromotion, &a, &b, &c)?;
+            check_ord_laws(&BySum, &a, &b, &c)?;
+            check_ord_laws(&ByDepth, &a, &b, &c)?;
+        }
+
+        #[test]
+        fn display_round_trip(
+            packet_datum in any_with::<PacketDatum>(
+                PacketParams::new().max_depth(6).integers(i32::MIN..=i32::MAX).strings("(?s).{0,8}")
+            )
+        ) {
+            check_display_round_trip(&packet_datum)?;
+        }
+
+        #[test]
+        fn dialect_round_trip(
+            packet_datum in any_with::<PacketDatum>(
+                PacketParams::new().max_depth(6).integers(i32::MIN..=i32::MAX).strings("(?s).{0,8}")
+            )
+        ) {
+            for dialect in [
+                Dialect::BRACKETS,
+                Dialect::BRACES.trailing_separator(true),
+                Dialect::S_EXPRESSION,
+                Dialect::new('<', '>').separator(Separator::Semicolon),
+            ] {
+                let text = dialect.display(&packet_datum).to_string();
+                prop_assert_eq!(dialect.parse(&text).ok(), Some(packet_datum.clone()));
+            }
+        }
+
+        #[test]
+        fn parsers_never_panic_on_packet_like_text(s in packet_like_strings()) {
+            check_parsers_never_panic(&s)?;
+        }
+
+        #[test]
+        fn parsers_never_panic_on_any_text(s in any::<String>()) {
+            check_parsers_never_panic(&s)?;
+        }
+
+        #[test]
+        fn parsers_accept_packet_strings(
+            s in packet_strings(PacketParams::new().strings("[a\"\\\\\n\u{0}é😀]{0,4}"))
+        ) {
+            prop_assert!(validate_str(&s).is_ok());
+            check_parsers_never_panic(&s)?;
+        }
+    }
+}
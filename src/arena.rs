@@ -0,0 +1,429 @@
+use crate::datum::{unescape, PacketDatum, PacketParseError, Token, Tokens};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+// Every list and atom of a parsed PacketDatum is its own Rc<RefCell<..>> heap allocation,
+// and every list owns a Vec of them on top of that.
+// For bulk work (millions of packets) that's a lot of small allocations and pointer chasing.
+//
+// PacketArena stores every packet in three flat buffers instead:
+// - nodes:    every list, integer and string of every packet, 12 bytes each
+// - children: the child node ids of every list, contiguous per list
+// - strings:  the (unescaped) text of every string, one after the other
+//
+// A list node is just a (start, len) window into children, a string node one into strings.
+// The parser writes straight into the arena: a list's children are collected on a scratch stack
+// while it's open and copied into children in one go when its ] is reached,
+// so every list's children end up next to each other.
+//
+// cmp works on node ids with the same rules as PacketDatum:
+// comparing an atom with a list compares it like a list holding just that atom,
+// without building that list.
+//
+// Node ids and the offsets into children and strings are u32 to keep nodes small,
+// so one arena holds at most u32::MAX nodes, child ids and bytes of string text.
+// Going past any of those panics instead of wrapping around into the wrong nodes.
+
+/// Arena Module:
+/// PacketArena: flat storage for large numbers of packets.
+///
+/// Holds up to u32::MAX nodes (lists and atoms), u32::MAX list items and u32::MAX bytes of strings
+/// across all its packets. Adding past that panics, start another arena before it gets there.
+///
+/// ex:
+/// let mut arena = PacketArena::new();
+/// let left = arena.parse("[[1],[2,3,4]]")?;
+/// let right = arena.parse("[[1],4]")?;
+/// arena.cmp(left, right) == Ordering::Less
+#[derive(Default)]
+pub struct PacketArena {
+    nodes: Vec<Node>,
+    children: Vec<NodeId>,
+    strings: String,
+    packets: Vec<PacketId>,
+    // scratch stack of the children of lists still being parsed
+    open_children: Vec<NodeId>,
+}
+
+/// A packet stored in a PacketArena.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PacketId(NodeId);
+
+type NodeId = u32;
+
+#[derive(Clone, Copy)]
+enum Node {
+    Integer(i32),
+    String { start: u32, len: u32 },
+    List { start: u32, len: u32 },
+}
+
+impl PacketArena {
+    pub fn new() -> Self {
+        PacketArena::default()
+    }
+
+    /// Parses a packet string straight into the arena.
+    ///
+    /// Accepts the same text as compare_str: commas and whitespace separate tokens.
+    /// Nothing is added to the arena if the packet is malformed.
+    pub fn parse(&mut self, s: &str) -> Result<PacketId, PacketParseError> {
+        let nodes_before = self.nodes.len();
+        let children_before = self.children.len();
+        let strings_before = self.strings.len();
+
+        match self.parse_packet(s) {
+            Ok(id) => {
+                self.packets.push(id);
+                Ok(id)
+            }
+            Err(e) => {
+                self.nodes.truncate(nodes_before);
+                self.children.truncate(children_before);
+                self.strings.truncate(strings_before);
+                self.open_children.clear();
+                Err(e)
+            }
+        }
+    }
+
+    fn parse_packet(&mut self, s: &str) -> Result<PacketId, PacketParseError> {
+        let mut tokens = Tokens::new(s);
+        // (node id of the open list, where its children start on the scratch stack)
+        let mut open_lists: Vec<(NodeId, usize)> = vec![];
+        let mut root = None;
+
+        while let Some(token) = tokens.next_token()? {
+            match token {
+                Token::Open => {
+                    let id = self.push_node(Node::List { start: 0, len: 0 });
+
+                    match open_lists.last() {
+                        Some(_) => self.open_children.push(id),
+                        None => root = Some(id),
+                    }
+
+                    open_lists.push((id, self.open_children.len()));
+                }
+                Token::Close => {
+                    // Tokens already rejects a ] with nothing to close
+                    let (id, children_start) = open_lists.pop().ok_or(PacketParseError)?;
+
+                    let start = offset(self.children.len(), "list items");
+                    self.children
+                        .extend(self.open_children.drain(children_start..));
+                    let len = offset(self.children.len(), "list items") - start;
+
+                    self.nodes[id as usize] = Node::List { start, len };
+                }
+                Token::Integer(i) => {
+                    let id = self.push_node(Node::Integer(i));
+                    self.open_children.push(id);
+                }
+                Token::String(content) => {
+                    let id = self.push_string(&unescape(content));
+                    self.open_children.push(id);
+                }
+            }
+        }
+
+        match (root, open_lists.is_empty()) {
+            (Some(root), true) => Ok(PacketId(root)),
+            _ => Err(PacketParseError),
+        }
+    }
+
+    fn push_node(&mut self, node: Node) -> NodeId {
+        let id = offset(self.nodes.len(), "nodes");
+        self.nodes.push(node);
+
+        id
+    }
+
+    fn push_string(&mut self, s: &str) -> NodeId {
+        let start = offset(self.strings.len(), "bytes of strings");
+        self.strings.push_str(s);
+        let len = offset(self.strings.len(), "bytes of strings") - start;
+
+        self.push_node(Node::String { start, len })
+    }
+
+    fn string_of(&self, start: u32, len: u32) -> &str {
+        &self.strings[start as usize..start as usize + len as usize]
+    }
+
+    /// Copies a PacketDatum into the arena.
+    pub fn push_datum(&mut self, packet_datum: &PacketDatum) -> PacketId {
+        let id = self.push_datum_node(packet_datum);
+        self.packets.push(PacketId(id));
+
+        PacketId(id)
+    }
+
+    fn push_datum_node(&mut self, packet_datum: &PacketDatum) -> NodeId {
+        match packet_datum {
+            PacketDatum::Integer(i) => self.push_node(Node::Integer(*i)),
+            PacketDatum::String(s) => self.push_string(s),
+            PacketDatum::List(l) => {
+                let id = self.push_node(Node::List { start: 0, len: 0 });
+
+                let child_ids: Vec<NodeId> = l
+                    .iter()
+                    .map(|child| self.push_datum_node(&child.borrow()))
+                    .collect();
+
+                let start = offset(self.children.len(), "list items");
+                self.children.extend(child_ids);
+                let len = offset(self.children.len(), "list items") - start;
+                self.nodes[id as usize] = Node::List { start, len };
+
+                id
+            }
+        }
+    }
+
+    /// Builds an owned PacketDatum out of a packet in the arena.
+    pub fn to_datum(&self, packet: PacketId) -> PacketDatum {
+        self.node_to_datum(packet.0)
+    }
+
+    fn node_to_datum(&self, id: NodeId) -> PacketDatum {
+        match self.nodes[id as usize] {
+            Node::Integer(i) => PacketDatum::Integer(i),
+            Node::String { start, len } => {
+                PacketDatum::String(self.string_of(start, len).to_string())
+            }
+            Node::List { .. } => PacketDatum::List(
+                self.children_of(id)
+                    .iter()
+                    .map(|child| Rc::new(RefCell::new(self.node_to_datum(*child))))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Day 13 ordering between two packets of this arena, same as PacketDatum::cmp.
+    pub fn cmp(&self, left: PacketId, right: PacketId) -> Ordering {
+        self.cmp_nodes(left.0, right.0)
+    }
+
+    fn cmp_nodes(&self, left: NodeId, right: NodeId) -> Ordering {
+        match (self.nodes[left as usize], self.nodes[right as usize]) {
+            (Node::Integer(i1), Node::Integer(i2)) => i1.cmp(&i2),
+            (
+                Node::String { start, len },
+                Node::String {
+                    start: start_2,
+                    len: len_2,
+                },
+            ) => self
+                .string_of(start, len)
+                .cmp(self.string_of(start_2, len_2)),
+            (Node::Integer(_), Node::String { .. }) => Ordering::Less,
+            (Node::String { .. }, Node::Integer(_)) => Ordering::Greater,
+            (Node::List { .. }, Node::List { .. }) => {
+                let (l1, l2) = (self.children_of(left), self.children_of(right));
+
+                for (child_1, child_2) in l1.iter().zip(l2) {
+                    let ordering = self.cmp_nodes(*child_1, *child_2);
+
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+
+                l1.len().cmp(&l2.len())
+            }
+            (_, Node::List { .. }) => self.cmp_atom_with_list(left, right),
+            (Node::List { .. }, _) => self.cmp_atom_with_list(right, left).reverse(),
+        }
+    }
+
+    // [atom] vs list
+    fn cmp_atom_with_list(&self, atom: NodeId, list: NodeId) -> Ordering {
+        match self.children_of(list).first() {
+            None => Ordering::Greater,
+            Some(first) => self
+                .cmp_nodes(atom, *first)
+                .then(1.cmp(&self.children_of(list).len())),
+        }
+    }
+
+    fn children_of(&self, list: NodeId) -> &[NodeId] {
+        match self.nodes[list as usize] {
+            Node::List { start, len } => {
+                &self.children[start as usize..start as usize + len as usize]
+            }
+            Node::Integer(_) | Node::String { .. } => &[],
+        }
+    }
+
+    /// Sorts packet ids by Day 13 rules. The arena itself doesn't move.
+    pub fn sort(&self, packets: &mut [PacketId]) {
+        packets.sort_by(|left, right| self.cmp(*left, *right));
+    }
+
+    /// Every packet added to the arena, in the order they were added.
+    pub fn packets(&self) -> &[PacketId] {
+        &self.packets
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Bytes held by the arena's buffers.
+    pub fn memory_bytes(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + self.children.capacity() * std::mem::size_of::<NodeId>()
+            + self.strings.capacity()
+            + self.packets.capacity() * std::mem::size_of::<PacketId>()
+            + self.open_children.capacity() * std::mem::size_of::<NodeId>()
+    }
+}
+
+// n as a u32 node id or offset, panics once the arena is full instead of wrapping
+fn offset(n: usize, what: &str) -> u32 {
+    u32::try_from(n)
+        .unwrap_or_else(|_| panic!("PacketArena can't hold more than {} {}", u32::MAX, what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::EXAMPLE_PACKETS;
+
+    fn example_arena() -> PacketArena {
+        let mut arena = PacketArena::new();
+
+        for s in EXAMPLE_PACKETS {
+            arena.parse(s).unwrap();
+        }
+
+        arena
+    }
+
+    #[test]
+    fn round_trip() {
+        let arena = example_arena();
+
+        for (s, packet) in EXAMPLE_PACKETS.iter().zip(arena.packets()) {
+            let expected: PacketDatum = s.parse().unwrap();
+            assert!(arena.to_datum(*packet) == expected);
+        }
+
+        let mut copy = PacketArena::new();
+        for s in EXAMPLE_PACKETS {
+            let packet_datum: PacketDatum = s.parse().unwrap();
+            let id = copy.push_datum(&packet_datum);
+            assert!(copy.to_datum(id) == packet_datum);
+        }
+    }
+
+    #[test]
+    fn cmp_matches_packet_datum() {
+        let arena = example_arena();
+        let packet_datums: Vec<PacketDatum> =
+            EXAMPLE_PACKETS.iter().map(|s| s.parse().unwrap()).collect();
+
+        for (i, packet_1) in arena.packets().iter().enumerate() {
+            for (j, packet_2) in arena.packets().iter().enumerate() {
+                let expected = packet_datums[i].cmp(&packet_datums[j]);
+                assert!(arena.cmp(*packet_1, *packet_2) == expected);
+            }
+        }
+    }
+
+    #[test]
+    // Part 2: dividers end up at positions 10 and 14
+    fn sort() {
+        let arena = example_arena();
+        let mut packets = arena.packets().to_vec();
+
+        arena.sort(&mut packets);
+
+        let dividers = &arena.packets()[16..];
+        let position = |divider| packets.iter().position(|p| p == divider).unwrap() + 1;
+        assert!(position(&dividers[0]) * position(&dividers[1]) == 140);
+    }
+
+    #[test]
+    // [3] == [[3]] and [3] < [[3,0]] without building [3]
+    fn integer_promotion() {
+        let mut arena = PacketArena::new();
+        let three = arena.parse("[3]").unwrap();
+        let nested_three = arena.parse("[[[3]]]").unwrap();
+        let longer = arena.parse("[[3,0]]").unwrap();
+        let empty = arena.parse("[[]]").unwrap();
+
+        assert!(arena.cmp(three, nested_three) == Ordering::Equal);
+        assert!(arena.cmp(three, longer) == Ordering::Less);
+        assert!(arena.cmp(longer, three) == Ordering::Greater);
+        assert!(arena.cmp(three, empty) == Ordering::Greater);
+    }
+
+    #[test]
+    fn strings() {
+        let packets = [
+            r#"[1,"temp",[2,3]]"#,
+            r#"[["temp"],"a\"b"]"#,
+            r#"["",[[]]]"#,
+            r#"[[["temp"]]]"#,
+            "[1]",
+        ];
+
+        let mut arena = PacketArena::new();
+        let packet_datums: Vec<PacketDatum> = packets.iter().map(|s| s.parse().unwrap()).collect();
+        let ids: Vec<PacketId> = packets.iter().map(|s| arena.parse(s).unwrap()).collect();
+
+        for (i, id_1) in ids.iter().enumerate() {
+            assert!(arena.to_datum(*id_1) == packet_datums[i]);
+
+            for (j, id_2) in ids.iter().enumerate() {
+                assert!(arena.cmp(*id_1, *id_2) == packet_datums[i].cmp(&packet_datums[j]));
+            }
+        }
+
+        assert!(arena.strings == "temptempa\"btemp");
+    }
+
+    #[test]
+    fn invalid_packets_leave_arena_unchanged() {
+        let mut arena = PacketArena::new();
+        let packet = arena.parse("[1,[2,3]]").unwrap();
+        let memory = (arena.nodes.len(), arena.children.len(), arena.strings.len());
+
+        for invalid in [
+            "[1,[2,3]",
+            "[1]]",
+            "[[1],a]",
+            "",
+            "1",
+            "[1][2]",
+            r#"["a"]]"#,
+        ] {
+            assert!(arena.parse(invalid).is_err());
+        }
+
+        assert!((arena.nodes.len(), arena.children.len(), arena.strings.len()) == memory);
+        assert!(arena.len() == 1);
+        assert!(arena.to_datum(packet) == "[1,[2,3]]".parse().unwrap());
+    }
+
+    #[test]
+    fn offsets_fit_in_u32() {
+        assert!(offset(u32::MAX as usize, "nodes") == u32::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "PacketArena can't hold more than 4294967295 nodes")]
+    // past u32::MAX is a panic, not an id that wrapped around to node 0
+    fn full_arena_panics() {
+        offset(u32::MAX as usize + 1, "nodes");
+    }
+}
@@ -0,0 +1,529 @@
+use crate::datum::PacketDatum;
+use crate::stream::{StreamError, StreamParser};
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+// Two ways to put packets on the wire:
+//
+// Lines: one packet per line, the packet text followed by \n, what `nc` or a person typing
+//   would send. \n is the frame boundary: a line is buffered until its \n arrives (never more
+//   than max_packet_size bytes of it) and then has to hold exactly one packet, so "[1][2]\n"
+//   or a packet spread over two lines is an error. A \r before the \n and blank lines are fine.
+//
+// LengthPrefixed: a u32 (big endian) byte length, then that many bytes of packet text.
+//   The frame is complete before it's parsed, so a peer sending one packet per frame is checked
+//   for exactly that, and a frame over the size limit is rejected from its length alone,
+//   before any of it is buffered.
+//
+// Both decoders enforce the same limits StreamParser does: packet size and list depth,
+// and both encoders refuse to send a packet longer than max_packet_size.
+
+/// Codec Module (cargo feature "tokio"):
+/// tokio-util Decoder & Encoder for PacketDatums, to use with Framed over a TcpStream.
+///
+/// ex:
+/// let mut framed = Framed::new(tcp_stream, PacketCodec::lines());
+/// framed.send(packet).await?;
+/// let reply: Option<Result<PacketDatum, PacketCodecError>> = framed.next().await;
+///
+/// Contains:
+/// - PacketCodec: one packet per line or length prefixed framing, with size & depth limits
+/// - PacketCodecError: io errors, malformed packets and frames over the limits
+pub struct PacketCodec {
+    framing: Framing,
+    max_packet_size: usize,
+    max_depth: usize,
+    // Lines only: how much of the buffered line is already known not to hold a \n
+    scanned: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// packet text, then \n: exactly one packet per line
+    Lines,
+    /// u32 big endian length, then the packet text
+    LengthPrefixed,
+}
+
+#[derive(Debug)]
+pub enum PacketCodecError {
+    Io(io::Error),
+    /// the packet text is malformed or breaks the depth limit
+    Packet(StreamError),
+    /// a frame (length prefixed, or a line so far) that's longer than max_packet_size,
+    /// or a packet too long to send
+    FrameTooLarge {
+        length: usize,
+        limit: usize,
+    },
+    /// a frame (length prefixed, or a line) that doesn't hold exactly one packet
+    NotOnePacket,
+}
+
+const LENGTH_BYTES: usize = 4;
+
+impl PacketCodec {
+    pub fn new(framing: Framing) -> Self {
+        PacketCodec {
+            framing,
+            max_packet_size: StreamParser::DEFAULT_MAX_PACKET_SIZE,
+            max_depth: StreamParser::DEFAULT_MAX_DEPTH,
+            scanned: 0,
+        }
+    }
+
+    pub fn lines() -> Self {
+        Self::new(Framing::Lines)
+    }
+
+    pub fn length_prefixed() -> Self {
+        Self::new(Framing::LengthPrefixed)
+    }
+
+    /// Longest packet in bytes (1 MiB by default), see StreamParser::max_packet_size.
+    pub fn max_packet_size(mut self, bytes: usize) -> Self {
+        self.max_packet_size = bytes;
+        self
+    }
+
+    /// Deepest list nesting, the root list is 1 (256 by default).
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    fn new_parser(&self) -> StreamParser {
+        StreamParser::new()
+            .max_packet_size(self.max_packet_size)
+            .max_depth(self.max_depth)
+    }
+
+    fn decode_line(&mut self, src: &mut BytesMut) -> Result<Option<PacketDatum>, PacketCodecError> {
+        loop {
+            let Some(newline) = src[self.scanned..].iter().position(|b| *b == b'\n') else {
+                self.scanned = src.len();
+
+                // room for a \r after the longest packet
+                if src.len() > self.max_packet_size + 1 {
+                    return Err(PacketCodecError::FrameTooLarge {
+                        length: src.len(),
+                        limit: self.max_packet_size,
+                    });
+                }
+
+                return Ok(None);
+            };
+
+            let line = src.split_to(self.scanned + newline + 1);
+            self.scanned = 0;
+
+            if let Some(packet_datum) = self.decode_line_frame(&line)? {
+                return Ok(Some(packet_datum));
+            }
+        }
+    }
+
+    // a whole line, \n (or \r\n) included if it has one; None if it's blank
+    fn decode_line_frame(&self, line: &[u8]) -> Result<Option<PacketDatum>, PacketCodecError> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+
+        if line.len() > self.max_packet_size {
+            return Err(PacketCodecError::FrameTooLarge {
+                length: line.len(),
+                limit: self.max_packet_size,
+            });
+        }
+
+        self.parse_frame(line).map(Some)
+    }
+
+    fn decode_frame(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<PacketDatum>, PacketCodecError> {
+        let Some(length) = src.get(..LENGTH_BYTES) else {
+            return Ok(None);
+        };
+
+        let length = u32::from_be_bytes(length.try_into().expect("4 bytes")) as usize;
+
+        if length > self.max_packet_size {
+            return Err(PacketCodecError::FrameTooLarge {
+                length,
+                limit: self.max_packet_size,
+            });
+        }
+
+        if src.len() < LENGTH_BYTES + length {
+            src.reserve(LENGTH_BYTES + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_BYTES);
+        let frame = src.split_to(length);
+
+        self.parse_frame(&frame).map(Some)
+    }
+
+    // every frame is parsed on its own: an error in one doesn't stick to the next,
+    // and error offsets are from the start of the frame
+    fn parse_frame(&self, frame: &[u8]) -> Result<PacketDatum, PacketCodecError> {
+        let mut parser = self.new_parser();
+        parser.feed(frame).map_err(PacketCodecError::Packet)?;
+
+        match (parser.finish(), parser.next_packet(), parser.next_packet()) {
+            (Ok(()), Some(packet_datum), None) => Ok(packet_datum),
+            (Err(e), None, _) => Err(PacketCodecError::Packet(e)),
+            _ => Err(PacketCodecError::NotOnePacket),
+        }
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = PacketDatum;
+    type Error = PacketCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<PacketDatum>, PacketCodecError> {
+        match self.framing {
+            Framing::Lines => self.decode_line(src),
+            Framing::LengthPrefixed => self.decode_frame(src),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<PacketDatum>, PacketCodecError> {
+        if let Some(packet_datum) = self.decode(src)? {
+            return Ok(Some(packet_datum));
+        }
+
+        match self.framing {
+            // the last line doesn't need a \n
+            Framing::Lines if !src.is_empty() => {
+                let line = src.split();
+                self.scanned = 0;
+
+                return self.decode_line_frame(&line);
+            }
+            Framing::Lines => (),
+            Framing::LengthPrefixed if !src.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the stream ended in the middle of a frame",
+                )
+                .into())
+            }
+            Framing::LengthPrefixed => (),
+        }
+
+        Ok(None)
+    }
+}
+
+impl Encoder<&PacketDatum> for PacketCodec {
+    type Error = PacketCodecError;
+
+    fn encode(
+        &mut self,
+        packet_datum: &PacketDatum,
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        let text = packet_datum.to_string();
+
+        // the peer would reject it anyway
+        let length = u32::try_from(text.len())
+            .ok()
+            .filter(|length| *length as usize <= self.max_packet_size)
+            .ok_or(PacketCodecError::FrameTooLarge {
+                length: text.len(),
+                limit: self.max_packet_size,
+            })?;
+
+        match self.framing {
+            Framing::Lines => {
+                dst.reserve(text.len() + 1);
+                dst.put_slice(text.as_bytes());
+                dst.put_u8(b'\n');
+            }
+            Framing::LengthPrefixed => {
+                dst.reserve(LENGTH_BYTES + text.len());
+                dst.put_u32(length);
+                dst.put_slice(text.as_bytes());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Encoder<PacketDatum> for PacketCodec {
+    type Error = PacketCodecError;
+
+    fn encode(&mut self, packet_datum: PacketDatum, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&packet_datum, dst)
+    }
+}
+
+impl fmt::Display for PacketCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketCodecError::Io(e) => write!(f, "{}", e),
+            PacketCodecError::Packet(e) => write!(f, "{}", e),
+            PacketCodecError::FrameTooLarge { length, limit } => write!(
+                f,
+                "frame of {} bytes is longer than the {} byte limit",
+                length, limit
+            ),
+            PacketCodecError::NotOnePacket => write!(f, "frame doesn't hold exactly one packet"),
+        }
+    }
+}
+
+impl std::error::Error for PacketCodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PacketCodecError::Io(e) => Some(e),
+            PacketCodecError::Packet(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PacketCodecError {
+    fn from(e: io::Error) -> Self {
+        PacketCodecError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::parse;
+    use futures::{SinkExt, StreamExt};
+    use tokio::io::{duplex, AsyncWriteExt};
+    use tokio_util::codec::{Framed, FramedRead, FramedWrite};
+
+    const PACKETS: [&str; 6] = [
+        "[1,1,3,1,1]",
+        "[[1],[2,3,4]]",
+        "[]",
+        "[[[]]]",
+        "[-2147483648,[2147483647]]",
+        "[1,[2,[3,[4,[5,6,7]]]],8,9]",
+    ];
+
+    // sends every packet from one end of a duplex stream and reads them back at the other
+    async fn round_trip(framing: Framing) {
+        // a small buffer, so packets are split across reads
+        let (client, server) = duplex(7);
+
+        let writer = async move {
+            let mut framed = FramedWrite::new(client, PacketCodec::new(framing));
+
+            for packet in PACKETS {
+                framed.send(parse(packet)).await.unwrap();
+            }
+        };
+
+        let reader = async move {
+            FramedRead::new(server, PacketCodec::new(framing))
+                .map(Result::unwrap)
+                .collect::<Vec<PacketDatum>>()
+                .await
+        };
+
+        let ((), received) = tokio::join!(writer, reader);
+
+        assert!(received == PACKETS.map(parse));
+    }
+
+    #[tokio::test]
+    async fn lines_round_trip() {
+        round_trip(Framing::Lines).await;
+    }
+
+    #[tokio::test]
+    async fn length_prefixed_round_trip() {
+        round_trip(Framing::LengthPrefixed).await;
+    }
+
+    #[tokio::test]
+    // a request/reply exchange over one Framed on each end
+    async fn request_reply() {
+        let (client, server) = duplex(64);
+        let mut client = Framed::new(client, PacketCodec::length_prefixed());
+        let mut server = Framed::new(server, PacketCodec::length_prefixed());
+
+        client.send(parse("[[2],[6]]")).await.unwrap();
+
+        let request = server.next().await.unwrap().unwrap();
+        server.send(&parse("[1]")).await.unwrap();
+
+        assert!(request == parse("[[2],[6]]"));
+        assert!(client.next().await.unwrap().unwrap() == parse("[1]"));
+    }
+
+    #[tokio::test]
+    // what someone would type: \r\n, blank lines, spaces
+    async fn lines_from_text() {
+        let (mut client, server) = duplex(64);
+
+        client
+            .write_all(b"[1, 2]\r\n\r\n  [[3]]\n[4")
+            .await
+            .unwrap();
+        client.write_all(b"2]\n").await.unwrap();
+        drop(client);
+
+        let received: Vec<PacketDatum> = FramedRead::new(server, PacketCodec::lines())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert!(received == vec![parse("[1,2]"), parse("[[3]]"), parse("[42]")]);
+    }
+
+    #[tokio::test]
+    async fn lines_errors() {
+        let (mut client, server) = duplex(64);
+        client.write_all(b"[1]\n[2,x]\n").await.unwrap();
+        drop(client);
+
+        let mut framed = FramedRead::new(server, PacketCodec::lines());
+
+        assert!(framed.next().await.unwrap().unwrap() == parse("[1]"));
+        // the offset is in the line
+        assert!(matches!(
+            framed.next().await,
+            Some(Err(PacketCodecError::Packet(StreamError::Parse {
+                offset: 3
+            })))
+        ));
+
+        // a packet cut off by the end of the stream
+        let (mut client, server) = duplex(64);
+        client.write_all(b"[1,[2").await.unwrap();
+        drop(client);
+
+        let mut framed = FramedRead::new(server, PacketCodec::lines());
+
+        assert!(matches!(
+            framed.next().await,
+            Some(Err(PacketCodecError::Packet(StreamError::UnexpectedEnd)))
+        ));
+    }
+
+    #[test]
+    // \n ends a frame: a line holds one packet, no more and no less
+    fn one_packet_per_line() {
+        for text in [&b"[1][2]\n"[..], b"[1,\n2]\n", b"[1] x\n"] {
+            let mut codec = PacketCodec::lines();
+            let mut buf = BytesMut::from(text);
+
+            assert!(codec.decode(&mut buf).is_err());
+        }
+
+        // a line isn't parsed until it's complete
+        let mut codec = PacketCodec::lines();
+        let mut buf = BytesMut::from(&b"\n [1,[2]]"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b" \r\n[3]\n");
+        assert!(codec.decode(&mut buf).unwrap() == Some(parse("[1,[2]]")));
+        assert!(codec.decode(&mut buf).unwrap() == Some(parse("[3]")));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn encode_limits() {
+        for mut codec in [PacketCodec::lines(), PacketCodec::length_prefixed()] {
+            codec = codec.max_packet_size(5);
+            let mut buf = BytesMut::new();
+
+            assert!(codec.encode(&parse("[1,2]"), &mut buf).is_ok());
+            assert!(matches!(
+                codec.encode(&parse("[1,23]"), &mut buf),
+                Err(PacketCodecError::FrameTooLarge {
+                    length: 6,
+                    limit: 5
+                })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let (mut client, server) = duplex(64);
+        client.write_all(b"[[[[1]]]]\n").await.unwrap();
+        drop(client);
+
+        let mut framed = FramedRead::new(server, PacketCodec::lines().max_depth(3));
+
+        assert!(matches!(
+            framed.next().await,
+            Some(Err(PacketCodecError::Packet(StreamError::TooDeep { .. })))
+        ));
+
+        // a line that goes on past the limit without a \n
+        let (mut client, server) = duplex(64);
+        client.write_all(&[b' '; 40]).await.unwrap();
+
+        let mut framed = FramedRead::new(server, PacketCodec::lines().max_packet_size(30));
+
+        assert!(matches!(
+            framed.next().await,
+            Some(Err(PacketCodecError::FrameTooLarge { limit: 30, .. }))
+        ));
+
+        // rejected from the length prefix, before the frame arrives
+        let (mut client, server) = duplex(64);
+        client.write_all(&1000u32.to_be_bytes()).await.unwrap();
+
+        let mut framed =
+            FramedRead::new(server, PacketCodec::length_prefixed().max_packet_size(100));
+
+        assert!(matches!(
+            framed.next().await,
+            Some(Err(PacketCodecError::FrameTooLarge {
+                length: 1000,
+                limit: 100
+            }))
+        ));
+    }
+
+    #[test]
+    fn frames() {
+        let mut codec = PacketCodec::length_prefixed();
+        let mut buf = BytesMut::new();
+
+        codec.encode(&parse("[1,[2]]"), &mut buf).unwrap();
+        assert!(&buf[..] == b"\0\0\0\x07[1,[2]]");
+
+        // two packets, or none, in one frame
+        for frame in [&b"\0\0\0\x06[1][2]"[..], b"\0\0\0\x02  "] {
+            let mut buf = BytesMut::from(frame);
+
+            assert!(matches!(
+                codec.decode(&mut buf),
+                Err(PacketCodecError::NotOnePacket)
+            ));
+        }
+
+        // the frame isn't complete yet
+        let mut buf = BytesMut::from(&b"\0\0\0\x07[1,["[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"2]]");
+        assert!(codec.decode(&mut buf).unwrap() == Some(parse("[1,[2]]")));
+        assert!(buf.is_empty());
+    }
+}
@@ -1,23 +1,55 @@
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::fmt;
 use std::rc::Rc;
 
 /// PacketDatum Enum:
 /// Nested Data structure that can take variants:
 /// - Vector of other PacketDatum
 /// - Integer
+/// - String: a quoted label, ex: [1,"temp",[2,3]]
+///
 /// author: Kenneth Salanga
 ///
 /// Contains:
+/// - Dialect Module
+///     - Dialect: other delimiters & separators for parsing and printing, ex: (1 (2 3) 4)
+/// - Escape Module
+///     - reading and writing quoted strings with JSON's escapes
+/// - Diagnostic Module
+///     - diagnose: where and why a packet string is malformed, rendered like a compiler error
+/// - Intern Module
+///     - PacketInterner: hash consing, every distinct subtree is built once and shared
+/// - PacketRef Module
+///     - PacketRef: borrowed view of a packet string that parses its children lazily
 /// - Parser Module
 ///     - takes a nested list of ints string & ouputs a List PacketDatum Variant
+/// - Recover Module
+///     - parse_recovering: keeps parsing past errors, a partial packet plus every error
+/// - Comparator Module
+///     - PacketComparator trait for other orderings, with sort/search/min/max helpers
+/// - Json Module
+///     - PacketDatum::from_json_str: parses exactly the JSON arrays of integers and strings
+///     - serde_json::Value conversions both ways (cargo feature "serde_json")
+/// - Hash Module
+///     - Hash for PacketDatum, PacketKey for hashing by Ord, and 128 bit fingerprints
+/// - Structure Module
+///     - validate (cycles & shared subtrees), try_push & deep_clone
+/// - Sync Module
+///     - SyncPacketDatum: immutable Arc based PacketDatum that's Send + Sync
+/// - Text Module
+///     - compare_str: compares packet strings directly, without building PacketDatums
+///     - validate_str: checks a packet string is well formed, without building a PacketDatum
+/// - Transform Module
+///     - normalize, flatten, remove_empty_lists & collapse_singletons
 
 // we a have a packet that contains a list of packet blocks:
-// those packet blocks can be: An integer, or another list of packet blocks.
+// those packet blocks can be: An integer, a string, or another list of packet blocks.
 #[derive(PartialEq, Eq, Clone)]
 pub enum PacketDatum {
     List(Vec<Rc<RefCell<PacketDatum>>>),
     Integer(i32),
+    String(String),
 }
 
 impl PacketDatum {
@@ -47,6 +79,7 @@ impl PacketDatum {
                 l.push(packet_datum);
             }
             PacketDatum::Integer(_) => panic!("cannot add item to Integer PacketDatum"),
+            PacketDatum::String(_) => panic!("cannot add item to String PacketDatum"),
         }
     }
 
@@ -59,22 +92,49 @@ impl PacketDatum {
     fn rc_int(i: i32) -> Rc<RefCell<PacketDatum>> {
         Rc::new(RefCell::new(PacketDatum::Integer(i)))
     }
+
+    #[allow(dead_code)]
+    fn rc_str(s: &str) -> Rc<RefCell<PacketDatum>> {
+        Rc::new(RefCell::new(PacketDatum::String(s.to_string())))
+    }
+
+    // an atom (Integer or String) compares equal to a list holding just that atom:
+    // 3 == [3] == [[3]] and "a" == ["a"]
+    // returns the atom a datum is interchangeable with under Ord, if there is one
+    pub(crate) fn promoted_atom(&self) -> Option<PacketDatum> {
+        match self {
+            PacketDatum::List(l) if l.len() == 1 => l[0].borrow().promoted_atom(),
+            PacketDatum::List(_) => None,
+            atom => Some(atom.clone()),
+        }
+    }
 }
 
-// can only compare lists with lists and integers with integers.
-// if we compare a list with an integer, that integer needs to become a list.
+// Ordering, for the integers it's the Day 13 rules:
+// - two integers: by value
+// - two strings: by their characters (Unicode code points), like str's Ord: "B" < "a" < "ab"
+// - an integer and a string: the integer is smaller, whatever the values
+// - two lists: item by item, a list that runs out first is smaller
+// - a list and an atom (integer or string): the atom becomes a list holding just it,
+//   so [1,"a"] < ["a"] < [["b"]] and "a" == ["a"]
+//
+// Packets without strings compare exactly as before, and strings never compare Equal
+// to anything that holds an integer.
 impl Ord for PacketDatum {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
-            (Self::Integer(i1), Self::Integer(i2)) => i1.cmp(&i2),
+            (Self::Integer(i1), Self::Integer(i2)) => i1.cmp(i2),
+            (Self::String(s1), Self::String(s2)) => s1.cmp(s2),
+            (Self::Integer(_), Self::String(_)) => Ordering::Less,
+            (Self::String(_), Self::Integer(_)) => Ordering::Greater,
             (Self::List(l1), Self::List(l2)) => l1.cmp(l2),
-            (Self::List(l1), Self::Integer(i2)) => {
-                let l2 = vec![Rc::new(RefCell::new(PacketDatum::Integer(*i2)))];
+            (Self::List(l1), atom) => {
+                let l2 = vec![Rc::new(RefCell::new(atom.clone()))];
                 l1.cmp(&l2)
             }
-            (Self::Integer(i1), Self::List(l2)) => {
-                let l1 = vec![Rc::new(RefCell::new(PacketDatum::Integer(*i1)))];
-                l1.cmp(&l2)
+            (atom, Self::List(l2)) => {
+                let l1 = vec![Rc::new(RefCell::new(atom.clone()))];
+                l1.cmp(l2)
             }
         }
     }
@@ -87,7 +147,71 @@ impl PartialOrd for PacketDatum {
     }
 }
 
+// prints the packet the way the puzzle input writes it, ex: [1,[2,3],[],"a\"b"]
+// so parsing the output gives back an equal PacketDatum
+impl fmt::Display for PacketDatum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketDatum::Integer(i) => write!(f, "{}", i),
+            PacketDatum::String(s) => escape::write_quoted(f, s),
+            PacketDatum::List(l) => {
+                write!(f, "[")?;
+
+                for (idx, packet_datum) in l.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{}", packet_datum.borrow())?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+// the derived Debug would print every Rc and RefCell in between
+impl fmt::Debug for PacketDatum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+mod comparator;
+mod diagnostic;
+mod dialect;
+mod escape;
+mod hash;
+mod intern;
+mod json;
+mod packet_ref;
 mod parser;
+mod recover;
+mod structure;
+mod sync;
+mod text;
+mod transform;
+
+pub use comparator::{
+    ByDepth, BySum, DayThirteen, LongerListFirst, NoPromotion, PacketComparator, ReverseIntegers,
+    Shortlex,
+};
+pub use diagnostic::{diagnose, Diagnostic, DiagnosticKind};
+pub use dialect::{Dialect, DialectDisplay, Separator};
+pub(crate) use escape::{scan_string, unescape, StringError};
+pub use hash::PacketKey;
+pub use intern::{InternStats, InternedPacket, PacketInterner};
+#[cfg(feature = "serde_json")]
+pub use json::JsonValueError;
+pub use packet_ref::{Children, PacketRef};
+pub use parser::PacketParseError;
+pub use recover::{parse_recovering, Recovered};
+pub use structure::StructureError;
+pub use sync::SyncPacketDatum;
+pub(crate) use text::compare_validated;
+pub use text::{compare_str, validate_str};
+pub(crate) use text::{Token, Tokens};
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
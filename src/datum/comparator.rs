@@ -0,0 +1,360 @@
+use super::PacketDatum;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+// Ord for PacketDatum is the Day 13 rules:
+// - integers compare by value, strings by their characters, and integers sort before strings
+// - lists compare item by item, and the list that runs out of items first is smaller
+// - comparing an atom (integer or string) with a list promotes the atom to a list: 3 vs [3]
+//
+// PacketComparator lets other orderings plug into the same sort/search/min/max helpers.
+// Most of the variants only flip one of the Day 13 rules, so they share one recursive
+// comparison (compare_with) driven by a small set of Rules.
+
+/// PacketComparator Trait:
+/// Any ordering over PacketDatums.
+///
+/// DayThirteen is the default and matches Ord for PacketDatum.
+/// Closures of the form Fn(&PacketDatum, &PacketDatum) -> Ordering are comparators too.
+///
+/// Provided helpers:
+/// - sort: stable sort of a slice
+/// - binary_search: search a slice sorted by this comparator
+/// - min / max: smallest / largest packet of an iterator
+pub trait PacketComparator {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering;
+
+    fn sort(&self, packets: &mut [PacketDatum]) {
+        packets.sort_by(|left, right| self.compare(left, right));
+    }
+
+    /// Ok(index) of a packet that compares Equal to the probe,
+    /// or Err(index) where the probe would be inserted to keep the slice sorted.
+    fn binary_search(&self, packets: &[PacketDatum], probe: &PacketDatum) -> Result<usize, usize> {
+        packets.binary_search_by(|packet| self.compare(packet, probe))
+    }
+
+    fn min<'a, I>(&self, packets: I) -> Option<&'a PacketDatum>
+    where
+        I: IntoIterator<Item = &'a PacketDatum>,
+    {
+        packets
+            .into_iter()
+            .min_by(|left, right| self.compare(left, right))
+    }
+
+    fn max<'a, I>(&self, packets: I) -> Option<&'a PacketDatum>
+    where
+        I: IntoIterator<Item = &'a PacketDatum>,
+    {
+        packets
+            .into_iter()
+            .max_by(|left, right| self.compare(left, right))
+    }
+
+    fn is_sorted(&self, packets: &[PacketDatum]) -> bool {
+        packets
+            .windows(2)
+            .all(|pair| self.compare(&pair[0], &pair[1]) != Ordering::Greater)
+    }
+}
+
+impl<F> PacketComparator for F
+where
+    F: Fn(&PacketDatum, &PacketDatum) -> Ordering,
+{
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        self(left, right)
+    }
+}
+
+/// The Advent of Code Day 13 rules, same as Ord for PacketDatum.
+#[derive(Clone, Copy, Default)]
+pub struct DayThirteen;
+
+impl PacketComparator for DayThirteen {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        left.cmp(right)
+    }
+}
+
+/// Day 13 rules but integers compare in reverse: [3] < [1].
+#[derive(Clone, Copy, Default)]
+pub struct ReverseIntegers;
+
+impl PacketComparator for ReverseIntegers {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        compare_with(
+            &Rules {
+                reverse_integers: true,
+                ..Rules::DAY_THIRTEEN
+            },
+            left,
+            right,
+        )
+    }
+}
+
+/// Day 13 rules but the list that runs out of items first is the larger one: [1,2] < [1].
+#[derive(Clone, Copy, Default)]
+pub struct LongerListFirst;
+
+impl PacketComparator for LongerListFirst {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        compare_with(
+            &Rules {
+                longer_first: true,
+                ..Rules::DAY_THIRTEEN
+            },
+            left,
+            right,
+        )
+    }
+}
+
+/// Lists compare by length first, then item by item: [9] < [1,1].
+///
+/// Integers are still promoted, so an integer compares like a list of length 1.
+#[derive(Clone, Copy, Default)]
+pub struct Shortlex;
+
+impl PacketComparator for Shortlex {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        compare_with(
+            &Rules {
+                shortlex: true,
+                ..Rules::DAY_THIRTEEN
+            },
+            left,
+            right,
+        )
+    }
+}
+
+/// Day 13 rules without promotion: an atom (integer or string) always sorts before a list,
+/// so [9] < [[]] and 3 != [3].
+#[derive(Clone, Copy, Default)]
+pub struct NoPromotion;
+
+impl PacketComparator for NoPromotion {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        compare_with(
+            &Rules {
+                promote_atoms: false,
+                ..Rules::DAY_THIRTEEN
+            },
+            left,
+            right,
+        )
+    }
+}
+
+/// Compares the sum of every integer in the packet (strings add nothing).
+/// Packets with the same sum are Equal.
+#[derive(Clone, Copy, Default)]
+pub struct BySum;
+
+impl PacketComparator for BySum {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        sum(left).cmp(&sum(right))
+    }
+}
+
+/// Compares how deeply the lists are nested: 3 and "a" have depth 0, [] and [3] depth 1, [[3]] depth 2.
+/// Packets with the same depth are Equal.
+#[derive(Clone, Copy, Default)]
+pub struct ByDepth;
+
+impl PacketComparator for ByDepth {
+    fn compare(&self, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+        depth(left).cmp(&depth(right))
+    }
+}
+
+struct Rules {
+    reverse_integers: bool,
+    longer_first: bool,
+    shortlex: bool,
+    // an atom (integer or string) compared with a list becomes [atom]
+    promote_atoms: bool,
+}
+
+impl Rules {
+    const DAY_THIRTEEN: Rules = Rules {
+        reverse_integers: false,
+        longer_first: false,
+        shortlex: false,
+        promote_atoms: true,
+    };
+}
+
+fn compare_with(rules: &Rules, left: &PacketDatum, right: &PacketDatum) -> Ordering {
+    match (left, right) {
+        (PacketDatum::Integer(i1), PacketDatum::Integer(i2)) => {
+            if rules.reverse_integers {
+                i2.cmp(i1)
+            } else {
+                i1.cmp(i2)
+            }
+        }
+        (PacketDatum::List(l1), PacketDatum::List(l2)) => compare_lists(rules, l1, l2),
+        (PacketDatum::List(l1), atom) => {
+            if !rules.promote_atoms {
+                return Ordering::Greater;
+            }
+
+            let l2 = vec![Rc::new(RefCell::new(atom.clone()))];
+            compare_lists(rules, l1, &l2)
+        }
+        (atom, PacketDatum::List(l2)) => {
+            if !rules.promote_atoms {
+                return Ordering::Less;
+            }
+
+            let l1 = vec![Rc::new(RefCell::new(atom.clone()))];
+            compare_lists(rules, &l1, l2)
+        }
+        // two strings, or an integer and a string
+        (atom1, atom2) => atom1.cmp(atom2),
+    }
+}
+
+fn compare_lists(
+    rules: &Rules,
+    l1: &[Rc<RefCell<PacketDatum>>],
+    l2: &[Rc<RefCell<PacketDatum>>],
+) -> Ordering {
+    let lengths = if rules.longer_first {
+        l2.len().cmp(&l1.len())
+    } else {
+        l1.len().cmp(&l2.len())
+    };
+
+    if rules.shortlex && lengths != Ordering::Equal {
+        return lengths;
+    }
+
+    for (packet_datum_1, packet_datum_2) in l1.iter().zip(l2) {
+        let ordering = compare_with(rules, &packet_datum_1.borrow(), &packet_datum_2.borrow());
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    lengths
+}
+
+fn sum(packet_datum: &PacketDatum) -> i64 {
+    match packet_datum {
+        PacketDatum::Integer(i) => *i as i64,
+        PacketDatum::String(_) => 0,
+        PacketDatum::List(l) => l.iter().map(|p| sum(&p.borrow())).sum(),
+    }
+}
+
+fn depth(packet_datum: &PacketDatum) -> usize {
+    match packet_datum {
+        PacketDatum::Integer(_) | PacketDatum::String(_) => 0,
+        PacketDatum::List(l) => 1 + l.iter().map(|p| depth(&p.borrow())).max().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::{parse, EXAMPLE_PACKETS};
+    use crate::datum::PacketDatum as pd;
+
+    #[test]
+    // Part 2: the dividers end up at 1-based positions 10 and 14
+    fn day_thirteen_sort() {
+        let mut packets: Vec<pd> = EXAMPLE_PACKETS.iter().map(|s| parse(s)).collect();
+
+        DayThirteen.sort(&mut packets);
+        assert!(DayThirteen.is_sorted(&packets));
+
+        let divider_1 = DayThirteen.binary_search(&packets, &parse("[[2]]"));
+        let divider_2 = DayThirteen.binary_search(&packets, &parse("[[6]]"));
+        assert!(divider_1 == Ok(9));
+        assert!(divider_2 == Ok(13));
+
+        assert!(DayThirteen.min(&packets) == Some(&parse("[]")));
+        assert!(DayThirteen.max(&packets) == Some(&parse("[9]")));
+    }
+
+    #[test]
+    fn day_thirteen_matches_ord() {
+        for s1 in EXAMPLE_PACKETS {
+            for s2 in EXAMPLE_PACKETS {
+                let (packet_1, packet_2) = (parse(s1), parse(s2));
+                let rules = compare_with(&Rules::DAY_THIRTEEN, &packet_1, &packet_2);
+
+                assert!(rules == packet_1.cmp(&packet_2));
+            }
+        }
+    }
+
+    #[test]
+    // [1,1,3,1,1] vs [1,1,5,1,1]
+    fn reverse_integers() {
+        let (packet_1, packet_2) = (parse("[1,1,3,1,1]"), parse("[1,1,5,1,1]"));
+
+        assert!(ReverseIntegers.compare(&packet_1, &packet_2) == Ordering::Greater);
+        // list lengths still follow Day 13
+        assert!(ReverseIntegers.compare(&parse("[7,7,7]"), &parse("[7,7,7,7]")) == Ordering::Less);
+        // strings aren't reversed, and still sort after integers
+        assert!(ReverseIntegers.compare(&parse(r#"["a"]"#), &parse(r#"["b"]"#)) == Ordering::Less);
+        assert!(ReverseIntegers.compare(&parse(r#"[1]"#), &parse(r#"["a"]"#)) == Ordering::Less);
+    }
+
+    #[test]
+    // [[4,4],4,4] vs [[4,4],4,4,4]
+    fn longer_list_first() {
+        let (packet_1, packet_2) = (parse("[[4,4],4,4]"), parse("[[4,4],4,4,4]"));
+
+        assert!(LongerListFirst.compare(&packet_1, &packet_2) == Ordering::Greater);
+        assert!(LongerListFirst.compare(&parse("[[]]"), &parse("[]")) == Ordering::Less);
+        // integers still follow Day 13
+        assert!(LongerListFirst.compare(&parse("[1,2]"), &parse("[3]")) == Ordering::Less);
+    }
+
+    #[test]
+    // [9] vs [[8,7,6]]: shortlex compares lengths before items
+    fn shortlex() {
+        assert!(Shortlex.compare(&parse("[9]"), &parse("[1,1]")) == Ordering::Less);
+        // 9 promotes to [9], length 1 < length 3
+        assert!(Shortlex.compare(&parse("[9]"), &parse("[[8,7,6]]")) == Ordering::Less);
+        assert!(Shortlex.compare(&parse("[1,2]"), &parse("[1,3]")) == Ordering::Less);
+    }
+
+    #[test]
+    fn no_promotion() {
+        assert!(NoPromotion.compare(&parse("[9]"), &parse("[[]]")) == Ordering::Less);
+        assert!(NoPromotion.compare(&parse("[[3]]"), &parse("[3]")) == Ordering::Greater);
+        assert!(NoPromotion.compare(&parse("[[1],4]"), &parse("[[1],[4]]")) == Ordering::Less);
+        assert!(NoPromotion.compare(&parse(r#"["z"]"#), &parse("[[]]")) == Ordering::Less);
+    }
+
+    #[test]
+    fn by_sum_and_depth() {
+        assert!(BySum.compare(&parse("[[1],[2,3,4]]"), &parse("[9]")) == Ordering::Greater);
+        assert!(BySum.compare(&parse("[[4,5]]"), &parse("[9]")) == Ordering::Equal);
+        assert!(BySum.compare(&parse(r#"[9,"a"]"#), &parse("[9]")) == Ordering::Equal);
+
+        assert!(ByDepth.compare(&parse("[[[]]]"), &parse("[9]")) == Ordering::Greater);
+        assert!(ByDepth.compare(&parse("[]"), &parse("[3]")) == Ordering::Equal);
+    }
+
+    #[test]
+    fn closures_are_comparators() {
+        let reverse_day_thirteen = |left: &pd, right: &pd| right.cmp(left);
+
+        let mut packets: Vec<pd> = EXAMPLE_PACKETS.iter().map(|s| parse(s)).collect();
+        reverse_day_thirteen.sort(&mut packets);
+
+        assert!(packets[0] == parse("[9]"));
+        assert!(reverse_day_thirteen.min(&packets) == Some(&parse("[9]")));
+    }
+}
@@ -0,0 +1,409 @@
+use super::parse_recovering;
+use std::fmt;
+use std::ops::Range;
+
+// PacketParseError only says that a packet is malformed. diagnose says where and why:
+// it's the first error the recovering parser finds. That parser keeps the same bracket stack
+// as from_str and reads the same grammar as Tokens (see recover.rs), so diagnose finds a problem
+// in exactly the strings from_str, validate_str and read_packets reject.
+
+/// Diagnostic:
+/// Where and why a packet string is malformed, rendered like a compiler error.
+///
+/// ex: diagnose("[1,[2,3]").unwrap().render(1, false)
+///
+/// error: unclosed list
+///  --> 1:1
+///   |
+/// 1 | [1,[2,3]
+///   | ^ this `[` is never closed
+///   |         - expected `]` here
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    source: String,
+    kind: DiagnosticKind,
+    span: Range<usize>,
+    label: String,
+    note: Option<(Range<usize>, String)>,
+}
+
+/// What's wrong with the packet, see Diagnostic::label for the details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// nothing but separators (commas and whitespace)
+    Empty,
+    /// the packet doesn't start with [
+    NotAList,
+    UnclosedList,
+    UnexpectedClose,
+    /// a - without digits, or an integer that doesn't fit in an i32
+    InvalidInteger,
+    /// a " without its closing "
+    UnterminatedString,
+    /// an invalid escape, or a control character that should have been escaped
+    InvalidString,
+    InvalidCharacter,
+    TextAfterPacket,
+}
+
+impl DiagnosticKind {
+    pub fn message(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Empty => "empty packet",
+            DiagnosticKind::NotAList => "a packet has to be a list",
+            DiagnosticKind::UnclosedList => "unclosed list",
+            DiagnosticKind::UnexpectedClose => "unexpected `]`",
+            DiagnosticKind::InvalidInteger => "invalid integer",
+            DiagnosticKind::UnterminatedString => "unterminated string",
+            DiagnosticKind::InvalidString => "invalid string",
+            DiagnosticKind::InvalidCharacter => "unexpected character",
+            DiagnosticKind::TextAfterPacket => "text after the end of the packet",
+        }
+    }
+
+    /// Short name, ex: "unclosed_list".
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Empty => "empty",
+            DiagnosticKind::NotAList => "not_a_list",
+            DiagnosticKind::UnclosedList => "unclosed_list",
+            DiagnosticKind::UnexpectedClose => "unexpected_close",
+            DiagnosticKind::InvalidInteger => "invalid_integer",
+            DiagnosticKind::UnterminatedString => "unterminated_string",
+            DiagnosticKind::InvalidString => "invalid_string",
+            DiagnosticKind::InvalidCharacter => "invalid_character",
+            DiagnosticKind::TextAfterPacket => "text_after_packet",
+        }
+    }
+}
+
+/// None if s is a well formed packet, otherwise the first problem in it.
+pub fn diagnose(s: &str) -> Option<Diagnostic> {
+    parse_recovering(s).errors.into_iter().next()
+}
+
+impl Diagnostic {
+    pub(super) fn new(
+        source: &str,
+        kind: DiagnosticKind,
+        span: Range<usize>,
+        label: impl Into<String>,
+    ) -> Self {
+        Diagnostic {
+            source: source.to_string(),
+            kind,
+            span,
+            label: label.into(),
+            note: None,
+        }
+    }
+
+    pub(super) fn with_note(mut self, span: Range<usize>, note: impl Into<String>) -> Self {
+        self.note = Some((span, note.into()));
+        self
+    }
+
+    pub fn kind(&self) -> DiagnosticKind {
+        self.kind
+    }
+
+    /// The packet text that was checked.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Byte range of the problem in source.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// What's wrong at span, ex: "this `[` is never closed".
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// A second place that explains the first, ex: where the missing `]` was expected.
+    pub fn note(&self) -> Option<(Range<usize>, &str)> {
+        self.note
+            .as_ref()
+            .map(|(span, note)| (span.clone(), note.as_str()))
+    }
+
+    /// Compiler style report. line_number is the line the packet came from (1 based),
+    /// color adds ANSI colors for a terminal.
+    pub fn render(&self, line_number: usize, color: bool) -> String {
+        let style = Style::new(color);
+        let primary = self.position(self.span.start);
+        let gutter = " ".repeat((line_number + self.last_line()).to_string().len());
+
+        let mut out = format!(
+            "{}: {}\n{}{} {}:{}\n{} {}\n",
+            style.error("error"),
+            style.bold(self.kind.message()),
+            gutter,
+            style.blue("-->"),
+            line_number + primary.0,
+            primary.1 + 1,
+            gutter,
+            style.blue("|"),
+        );
+
+        let mut annotations = vec![(self.span.clone(), self.label.as_str(), '^')];
+        if let Some((span, note)) = &self.note {
+            annotations.push((span.clone(), note, '-'));
+        }
+
+        let mut printed_line = None;
+
+        for (span, label, marker) in annotations {
+            let (line, column) = self.position(span.start);
+
+            if printed_line != Some(line) {
+                let text = self.source.lines().nth(line).unwrap_or("");
+                let number = (line_number + line).to_string();
+
+                out += &format!(
+                    "{}{} {} {}\n",
+                    style.blue(&number),
+                    " ".repeat(gutter.len() - number.len()),
+                    style.blue("|"),
+                    text
+                );
+                printed_line = Some(line);
+            }
+
+            let width = self.source[span.clone()].chars().count().max(1);
+            let markers = marker.to_string().repeat(width);
+            let markers = match marker {
+                '^' => style.error(&markers),
+                _ => style.blue(&markers),
+            };
+
+            out += &format!(
+                "{} {} {}{} {}\n",
+                gutter,
+                style.blue("|"),
+                " ".repeat(column),
+                markers,
+                label
+            );
+        }
+
+        out
+    }
+
+    // (line, column) of a byte offset, both 0 based, column in chars
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+
+        (line, before[line_start..].chars().count())
+    }
+
+    fn last_line(&self) -> usize {
+        let note_start = self.note.as_ref().map_or(0, |(span, _)| span.start);
+
+        self.position(self.span.start.max(note_start)).0
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.message(), self.label)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn new(color: bool) -> Self {
+        Style { color }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text.to_string(),
+        }
+    }
+
+    fn error(&self, text: &str) -> String {
+        self.paint("1;31", text)
+    }
+
+    fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    fn blue(&self, text: &str) -> String {
+        self.paint("1;34", text)
+    }
+}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for Diagnostic {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(format!("packet::{}", self.kind.code())))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let primary =
+            miette::LabeledSpan::new_primary_with_span(Some(self.label.clone()), self.span.clone());
+        let note = self.note.as_ref().map(|(span, note)| {
+            miette::LabeledSpan::new_with_span(Some(note.clone()), span.clone())
+        });
+
+        Some(Box::new(std::iter::once(primary).chain(note)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::validate_str;
+
+    fn kind(s: &str) -> Option<DiagnosticKind> {
+        diagnose(s).map(|diagnostic| diagnostic.kind())
+    }
+
+    #[test]
+    fn well_formed_packets() {
+        for s in [
+            "[]",
+            "[1,[2,[3]],-4]",
+            " [ 1 , [ ] ]\n",
+            "[[[]]]",
+            "[\"a\\\"]\",[\"\"]]",
+        ] {
+            assert!(diagnose(s).is_none());
+            assert!(validate_str(s).is_ok());
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(kind("") == Some(DiagnosticKind::Empty));
+        assert!(kind("  ") == Some(DiagnosticKind::Empty));
+        assert!(kind("1") == Some(DiagnosticKind::NotAList));
+        assert!(kind("]") == Some(DiagnosticKind::NotAList));
+        assert!(kind("[1,[2,3]") == Some(DiagnosticKind::UnclosedList));
+        assert!(kind("[1,2]]") == Some(DiagnosticKind::UnexpectedClose));
+        assert!(kind(",,") == Some(DiagnosticKind::Empty));
+        assert!(kind("[-]") == Some(DiagnosticKind::InvalidInteger));
+        assert!(kind("[2147483648]") == Some(DiagnosticKind::InvalidInteger));
+        assert!(kind("[1,a]") == Some(DiagnosticKind::InvalidCharacter));
+        assert!(kind("[1,\"a]") == Some(DiagnosticKind::UnterminatedString));
+        assert!(kind("[\"\\q\"]") == Some(DiagnosticKind::InvalidString));
+        assert!(kind("[\"a\tb\"]") == Some(DiagnosticKind::InvalidString));
+        assert!(kind("[1][2]") == Some(DiagnosticKind::TextAfterPacket));
+    }
+
+    #[test]
+    fn spans_and_notes() {
+        let diagnostic = diagnose("[1,[2,3]").unwrap();
+        assert!(diagnostic.span() == (0..1));
+        assert!(diagnostic.label() == "this `[` is never closed");
+        assert!(diagnostic.note() == Some((8..8, "expected `]` here")));
+
+        let diagnostic = diagnose("[1,2]]").unwrap();
+        assert!(diagnostic.span() == (5..6));
+        assert!(diagnostic.note() == Some((4..5, "the packet ends here")));
+
+        let diagnostic = diagnose("[1] , x").unwrap();
+        assert!(diagnostic.span() == (6..7));
+        assert!(diagnostic.label() == "text after the packet");
+        assert!(diagnostic.note() == Some((2..3, "the packet ends here")));
+
+        let diagnostic = diagnose("[99999999999]").unwrap();
+        assert!(diagnostic.span() == (1..12));
+    }
+
+    #[test]
+    fn render() {
+        let rendered = diagnose("[1,[2,3]").unwrap().render(1, false);
+
+        let expected = "\
+error: unclosed list
+ --> 1:1
+  |
+1 | [1,[2,3]
+  | ^ this `[` is never closed
+  |         - expected `]` here
+";
+        assert!(rendered == expected);
+
+        let rendered = diagnose("[[1],[22 3]]]").unwrap().render(41, false);
+
+        let expected = "\
+error: unexpected `]`
+  --> 41:13
+   |
+41 | [[1],[22 3]]]
+   |             ^ no list is open here
+   |            - the packet ends here
+";
+        assert!(rendered == expected);
+    }
+
+    #[test]
+    fn render_with_color() {
+        let rendered = diagnose("[1,a]").unwrap().render(1, true);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m: \x1b[1munexpected character\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m `a` can't be part of a packet"));
+    }
+
+    #[test]
+    // one grammar: diagnose finds a problem exactly when validate_str (and from_str) rejects
+    fn agrees_with_validate_str() {
+        for s in [
+            "",
+            ",",
+            "1",
+            "]",
+            "[1,[2,3]",
+            "[1,2]]",
+            "[-]",
+            "[2147483648]",
+            "[1,a]",
+            "[1][2]",
+            "x[1]",
+            "[1 2]",
+            "[1,]",
+            "[,1]",
+            "[1,,2]",
+            ",[],",
+            "[[1][2]]",
+            "[1\u{b}2]",
+            "[1\u{a0}2]",
+            "[\"a\"\"b\"]",
+        ] {
+            assert!(diagnose(s).is_none() == validate_str(s).is_ok());
+            assert!(diagnose(s).is_none() == s.parse::<crate::datum::PacketDatum>().is_ok());
+        }
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn miette_labels() {
+        use miette::Diagnostic as _;
+
+        let diagnostic = diagnose("[1,[2,3]").unwrap();
+        let labels: Vec<miette::LabeledSpan> = diagnostic.labels().unwrap().collect();
+
+        assert!(labels.len() == 2);
+        assert!(labels[0].label() == Some("this `[` is never closed"));
+        assert!(labels[0].offset() == 0 && labels[0].primary());
+        assert!(labels[1].label() == Some("expected `]` here"));
+        assert!(diagnostic.code().unwrap().to_string() == "packet::unclosed_list");
+    }
+}
@@ -0,0 +1,375 @@
+use super::escape::{scan_string, unescape, write_quoted};
+use super::{PacketDatum, PacketParseError};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// The same nested integers written with other punctuation:
+//     [1,[2,3],4]    Dialect::BRACKETS (what Display prints)
+//     (1 (2 3) 4)    Dialect::S_EXPRESSION
+//     {1,{2,3},4}    Dialect::BRACES
+//
+// A dialect is the list delimiters, the separator between items and whether a separator may
+// follow the last item. Parsing is strict about the separator: exactly one between two items,
+// and (unless trailing separators are on) none after the last one or before the first.
+// ASCII whitespace is allowed between any two tokens. With the Whitespace separator it's also
+// what separates items, but only integers need it: "(1(2 3)4)" is fine, "(1-2)" isn't.
+// Strings are quoted with the same escapes in every dialect, so "(" and "," inside them are text.
+//
+// That's a grammar of its own, not the packet grammar from_str reads (text.rs), which takes
+// any number of commas and whitespace anywhere. Whitespace is the same ASCII whitespace in both,
+// so what Dialect::BRACKETS accepts, from_str accepts too and builds the same packet from.
+//
+// One pass over the chars with a stack of the open lists' children.
+
+/// Dialect:
+/// Delimiters and separators for reading and writing packets in other notations.
+///
+/// ex:
+/// let packet = Dialect::S_EXPRESSION.parse("(1 (2 3) 4)")?;
+/// Dialect::BRACES.display(&packet).to_string() == "{1,{2,3},4}"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dialect {
+    open: char,
+    close: char,
+    separator: Separator,
+    trailing_separator: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Separator {
+    Comma,
+    Semicolon,
+    /// any run of whitespace, printed as one space
+    Whitespace,
+}
+
+impl Separator {
+    fn char(&self) -> Option<char> {
+        match self {
+            Separator::Comma => Some(','),
+            Separator::Semicolon => Some(';'),
+            Separator::Whitespace => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Separator::Comma => ",",
+            Separator::Semicolon => ";",
+            Separator::Whitespace => " ",
+        }
+    }
+}
+
+impl Dialect {
+    /// [1,[2,3],4]
+    pub const BRACKETS: Dialect = Dialect::new('[', ']');
+    /// {1,{2,3},4}
+    pub const BRACES: Dialect = Dialect::new('{', '}');
+    /// (1 (2 3) 4)
+    pub const S_EXPRESSION: Dialect = Dialect::new('(', ')').separator(Separator::Whitespace);
+
+    /// Lists between open and close, items separated by commas, no trailing comma.
+    ///
+    /// Panics if a delimiter could be read as part of something else:
+    /// open == close, or either is whitespace, a digit, '-', ',', ';' or '"'.
+    pub const fn new(open: char, close: char) -> Self {
+        assert!(
+            open != close && is_delimiter(open) && is_delimiter(close),
+            "delimiters have to be two different characters that aren't whitespace, digits or any of - , ; \""
+        );
+
+        Dialect {
+            open,
+            close,
+            separator: Separator::Comma,
+            trailing_separator: false,
+        }
+    }
+
+    pub const fn separator(mut self, separator: Separator) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// The parser accepts a separator after the last item and the printer writes one.
+    /// Makes no difference with Separator::Whitespace.
+    pub const fn trailing_separator(mut self, trailing_separator: bool) -> Self {
+        self.trailing_separator = trailing_separator;
+        self
+    }
+
+    /// Parses a packet written in this dialect into a List PacketDatum.
+    pub fn parse(&self, s: &str) -> Result<PacketDatum, PacketParseError> {
+        let mut chars = s.char_indices().peekable();
+        let mut open_lists: Vec<Vec<Rc<RefCell<PacketDatum>>>> = vec![];
+        let mut root = None;
+
+        // an item just ended in the innermost list / a separator came after it
+        let mut after_item = false;
+        let mut after_separator = false;
+        // an integer ends right here, so another one can't start without whitespace
+        let mut touching_integer = false;
+
+        while let Some((idx, c)) = chars.next() {
+            let ends_integer = touching_integer;
+            touching_integer = false;
+
+            if c.is_ascii_whitespace() {
+                continue;
+            }
+
+            if root.is_some() {
+                return Err(PacketParseError);
+            }
+
+            if Some(c) == self.separator.char() {
+                if !after_item {
+                    return Err(PacketParseError);
+                }
+
+                after_item = false;
+                after_separator = true;
+                continue;
+            }
+
+            if open_lists.is_empty() && c != self.open {
+                return Err(PacketParseError);
+            }
+
+            let needs_separator = after_item && self.separator != Separator::Whitespace;
+
+            let item = if c == self.open {
+                if needs_separator {
+                    return Err(PacketParseError);
+                }
+
+                open_lists.push(vec![]);
+                after_item = false;
+                after_separator = false;
+                continue;
+            } else if c == self.close {
+                if after_separator && !self.trailing_separator {
+                    return Err(PacketParseError);
+                }
+
+                PacketDatum::List(open_lists.pop().expect("a list is open"))
+            } else if c == '-' || c.is_ascii_digit() {
+                if needs_separator || ends_integer {
+                    return Err(PacketParseError);
+                }
+
+                let mut end = idx + 1;
+                while let Some((_, '0'..='9')) = chars.peek() {
+                    chars.next();
+                    end += 1;
+                }
+
+                touching_integer = true;
+                PacketDatum::Integer(s[idx..end].parse().map_err(|_| PacketParseError)?)
+            } else if c == '"' {
+                if needs_separator {
+                    return Err(PacketParseError);
+                }
+
+                let end = scan_string(s, idx).map_err(|_| PacketParseError)?;
+                while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+
+                PacketDatum::String(unescape(&s[idx + 1..end - 1]))
+            } else {
+                return Err(PacketParseError);
+            };
+
+            after_item = true;
+            after_separator = false;
+
+            match open_lists.last_mut() {
+                Some(children) => children.push(Rc::new(RefCell::new(item))),
+                None => root = Some(item),
+            }
+        }
+
+        root.ok_or(PacketParseError)
+    }
+
+    /// Prints packet_datum in this dialect, ex: Dialect::BRACES.display(&packet).to_string()
+    pub fn display<'a>(&'a self, packet_datum: &'a PacketDatum) -> DialectDisplay<'a> {
+        DialectDisplay {
+            dialect: self,
+            packet_datum,
+        }
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect::BRACKETS
+    }
+}
+
+const fn is_delimiter(c: char) -> bool {
+    !(c.is_whitespace() || c.is_ascii_digit() || matches!(c, '-' | ',' | ';' | '"'))
+}
+
+/// A packet printed in a dialect, see Dialect::display.
+pub struct DialectDisplay<'a> {
+    dialect: &'a Dialect,
+    packet_datum: &'a PacketDatum,
+}
+
+impl fmt::Display for DialectDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.packet_datum {
+            PacketDatum::Integer(i) => write!(f, "{}", i),
+            PacketDatum::String(s) => write_quoted(f, s),
+            PacketDatum::List(l) => {
+                let separator = self.dialect.separator.as_str();
+
+                write!(f, "{}", self.dialect.open)?;
+
+                for (idx, packet_datum) in l.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, "{}", separator)?;
+                    }
+
+                    write!(f, "{}", self.dialect.display(&packet_datum.borrow()))?;
+                }
+
+                if self.dialect.trailing_separator
+                    && !l.is_empty()
+                    && self.dialect.separator != Separator::Whitespace
+                {
+                    write!(f, "{}", separator)?;
+                }
+
+                write!(f, "{}", self.dialect.close)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::parse;
+
+    #[test]
+    fn dialects() {
+        let packet = parse("[1,[2,3],4,[],[-5,[[]]]]");
+
+        for (dialect, text) in [
+            (Dialect::BRACKETS, "[1,[2,3],4,[],[-5,[[]]]]"),
+            (Dialect::BRACES, "{1,{2,3},4,{},{-5,{{}}}}"),
+            (Dialect::S_EXPRESSION, "(1 (2 3) 4 () (-5 (())))"),
+            (
+                Dialect::new('<', '>').separator(Separator::Semicolon),
+                "<1;<2;3>;4;<>;<-5;<<>>>>",
+            ),
+            (
+                Dialect::BRACKETS.trailing_separator(true),
+                "[1,[2,3,],4,[],[-5,[[],],],]",
+            ),
+        ] {
+            assert!(dialect.display(&packet).to_string() == text);
+            assert!(dialect.parse(text).unwrap() == packet);
+        }
+
+        assert!(Dialect::BRACKETS.display(&packet).to_string() == packet.to_string());
+    }
+
+    #[test]
+    fn whitespace() {
+        let sexp = Dialect::S_EXPRESSION;
+
+        // only integers need whitespace between them
+        assert!(sexp.parse("(1(2 3)4)").unwrap() == parse("[1,[2,3],4]"));
+        assert!(sexp.parse(" (\n\t1   2\r\n) ").unwrap() == parse("[1,2]"));
+
+        // whitespace around the tokens of the other dialects
+        assert!(Dialect::BRACES.parse(" { 1 , { 2 } } ").unwrap() == parse("[1,[2]]"));
+
+        // ASCII whitespace only, like the packet grammar
+        assert!(sexp.parse("(1\u{a0}2)").is_err());
+        assert!(Dialect::BRACKETS.parse("[1,\u{a0}2]").is_err());
+    }
+
+    #[test]
+    fn trailing_separator() {
+        let trailing = Dialect::BRACES.trailing_separator(true);
+
+        // optional, not required
+        assert!(trailing.parse("{1,{2,},}").unwrap() == parse("[1,[2]]"));
+        assert!(trailing.parse("{1,{2}}").unwrap() == parse("[1,[2]]"));
+
+        assert!(Dialect::BRACES.parse("{1,}").is_err());
+        assert!(trailing.parse("{,}").is_err());
+        assert!(trailing.parse("{1,,}").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        for (dialect, text) in [
+            (Dialect::BRACES, "[1]"),
+            (Dialect::BRACES, "{1 2}"),
+            (Dialect::BRACES, "{1;2}"),
+            (Dialect::BRACES, "{,1}"),
+            (Dialect::BRACES, "{1,,2}"),
+            (Dialect::BRACES, "{1}{2}"),
+            (Dialect::BRACES, "{1{2}}"),
+            (Dialect::BRACES, "{1,{2}"),
+            (Dialect::BRACES, "{1}}"),
+            (Dialect::BRACES, "1"),
+            (Dialect::BRACES, ""),
+            (Dialect::S_EXPRESSION, "(1,2)"),
+            (Dialect::S_EXPRESSION, "(1-2)"),
+            (Dialect::S_EXPRESSION, "(-)"),
+            (Dialect::S_EXPRESSION, "(2147483648)"),
+            (Dialect::S_EXPRESSION, "(a)"),
+        ] {
+            assert!(dialect.parse(text).is_err());
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let packet = parse(r#"[1,"a b",["(x)",";"],"\"{,}"]"#);
+
+        for (dialect, text) in [
+            (Dialect::BRACES, r#"{1,"a b",{"(x)",";"},"\"{,}"}"#),
+            (Dialect::S_EXPRESSION, r#"(1 "a b" ("(x)" ";") "\"{,}")"#),
+        ] {
+            assert!(dialect.display(&packet).to_string() == text);
+            assert!(dialect.parse(text).unwrap() == packet);
+        }
+
+        // a string ends where its quote closes, so it doesn't need whitespace around it
+        assert!(
+            Dialect::S_EXPRESSION.parse(r#"(1"a"("b"))"#).unwrap() == parse(r#"[1,"a",["b"]]"#)
+        );
+
+        for (dialect, text) in [
+            (Dialect::BRACES, r#"{"a" "b"}"#),
+            (Dialect::BRACES, r#"{"a}"#),
+            (Dialect::S_EXPRESSION, r#"("\q")"#),
+        ] {
+            assert!(dialect.parse(text).is_err());
+        }
+    }
+
+    #[test]
+    fn other_characters() {
+        let dialect = Dialect::new('⟨', '⟩').separator(Separator::Whitespace);
+
+        let packet = dialect.parse("⟨1 ⟨2 3⟩ ⟨⟩⟩").unwrap();
+
+        assert!(packet == parse("[1,[2,3],[]]"));
+        assert!(dialect.display(&packet).to_string() == "⟨1 ⟨2 3⟩ ⟨⟩⟩");
+    }
+
+    #[test]
+    #[should_panic]
+    fn ambiguous_delimiters() {
+        Dialect::new('(', '(');
+    }
+}
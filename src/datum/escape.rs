@@ -0,0 +1,231 @@
+use std::fmt;
+use std::ops::Range;
+
+// String atoms are written the way JSON writes strings: between double quotes, with
+//     \" \\ \/ \b \f \n \r \t and \uXXXX
+// as the escapes. \uXXXX is a UTF-16 code unit, so a character outside the BMP is a surrogate
+// pair (😀) and a lone surrogate is an error. Every other character stands for itself,
+// except the control characters U+0000 to U+001F: those have to be escaped, so a packet never
+// spans more than one line.
+//
+// Every parser that reads string atoms finds the end of one with scan_string and decodes it
+// with unescape, so they agree on where a string ends and what it stands for.
+// That's all this file makes them share: what goes around the strings is the packet grammar
+// (see text.rs) for most of them, and a grammar of their own for JSON, dialects and templates.
+//
+// The printer escapes ", \ and the control characters (\n, \r and \t by name, the rest as \u00XX)
+// and writes everything else as is, so parsing what it prints gives back the same string.
+
+/// Why the string starting at a " isn't a valid string atom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StringError {
+    /// no closing "
+    Unterminated,
+    /// a \ that isn't one of the escapes (or a \u that isn't a character), its span
+    InvalidEscape(Range<usize>),
+    /// offset of a control character that should have been escaped
+    ControlCharacter(usize),
+}
+
+/// The offset just after the closing " of the string whose opening " is at start.
+pub(crate) fn scan_string(s: &str, start: usize) -> Result<usize, StringError> {
+    debug_assert!(s.as_bytes()[start] == b'"');
+
+    let bytes = s.as_bytes();
+    let mut pos = start + 1;
+
+    loop {
+        match bytes.get(pos) {
+            None => return Err(StringError::Unterminated),
+            Some(b'"') => return Ok(pos + 1),
+            Some(b'\\') => pos = escape(bytes, pos)?.1,
+            Some(c) if *c < 0x20 => return Err(StringError::ControlCharacter(pos)),
+            // the bytes of a multi byte char are all >= 0x80, none of them is " or \
+            Some(_) => pos += 1,
+        }
+    }
+}
+
+/// The characters a scanned string stands for, content is what's between the quotes.
+pub(crate) fn decoded_chars(content: &str) -> DecodedChars<'_> {
+    DecodedChars { content, pos: 0 }
+}
+
+/// content (what's between the quotes of a scanned string) with its escapes replaced.
+pub(crate) fn unescape(content: &str) -> String {
+    match content.contains('\\') {
+        true => decoded_chars(content).collect(),
+        false => content.to_string(),
+    }
+}
+
+pub(crate) struct DecodedChars<'a> {
+    content: &'a str,
+    pos: usize,
+}
+
+impl Iterator for DecodedChars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.content[self.pos..].chars().next()?;
+
+        if c != '\\' {
+            self.pos += c.len_utf8();
+            return Some(c);
+        }
+
+        let (c, end) = escape(self.content.as_bytes(), self.pos).expect("content was scanned");
+        self.pos = end;
+
+        Some(c)
+    }
+}
+
+// the escape whose \ is at pos: the character it stands for and the offset after it
+fn escape(bytes: &[u8], pos: usize) -> Result<(char, usize), StringError> {
+    // the span ends on a char boundary, so it can be sliced out of the text for error messages
+    let invalid = |end: usize| {
+        let mut end = end.min(bytes.len());
+        while bytes.get(end).is_some_and(|b| b & 0xc0 == 0x80) {
+            end += 1;
+        }
+
+        StringError::InvalidEscape(pos..end)
+    };
+
+    let c = match bytes.get(pos + 1) {
+        Some(b'"') => '"',
+        Some(b'\\') => '\\',
+        Some(b'/') => '/',
+        Some(b'b') => '\u{8}',
+        Some(b'f') => '\u{c}',
+        Some(b'n') => '\n',
+        Some(b'r') => '\r',
+        Some(b't') => '\t',
+        Some(b'u') => {
+            let high = code_unit(bytes, pos + 2).ok_or_else(|| invalid(pos + 6))?;
+
+            if !(0xd800..0xdc00).contains(&high) {
+                return char::from_u32(high as u32)
+                    .map(|c| (c, pos + 6))
+                    .ok_or_else(|| invalid(pos + 6));
+            }
+
+            // a high surrogate, the low one has to follow straight away
+            let low = match (bytes.get(pos + 6..pos + 8), code_unit(bytes, pos + 8)) {
+                (Some(b"\\u"), Some(low)) if (0xdc00..0xe000).contains(&low) => low,
+                _ => return Err(invalid(pos + 6)),
+            };
+
+            let c = 0x10000 + (((high as u32) - 0xd800) << 10) + ((low as u32) - 0xdc00);
+
+            return Ok((
+                char::from_u32(c).expect("a surrogate pair is a char"),
+                pos + 12,
+            ));
+        }
+        // a \ at the very end: the " that would close the string is missing
+        None => return Err(StringError::Unterminated),
+        Some(_) => return Err(invalid(pos + 2)),
+    };
+
+    Ok((c, pos + 2))
+}
+
+// 4 hex digits at pos
+fn code_unit(bytes: &[u8], pos: usize) -> Option<u16> {
+    let digits = bytes.get(pos..pos + 4)?;
+
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    u16::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// Writes s between quotes, escaped so scan_string reads it back: a"b is written "a\"b"
+pub(crate) fn write_quoted(f: &mut impl fmt::Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quoted(s: &str) -> String {
+        let mut out = String::new();
+        write_quoted(&mut out, s).unwrap();
+        out
+    }
+
+    // the content of a valid string literal, unescaped
+    fn read(literal: &str) -> Result<String, StringError> {
+        let end = scan_string(literal, 0)?;
+        assert!(end == literal.len());
+
+        Ok(unescape(&literal[1..end - 1]))
+    }
+
+    #[test]
+    fn escapes() {
+        for (literal, expected) in [
+            (r#""""#, ""),
+            (r#""temp""#, "temp"),
+            (r#""a\"b\\c\/d""#, "a\"b\\c/d"),
+            (r#""\b\f\n\r\t""#, "\u{8}\u{c}\n\r\t"),
+            (r#""Aé€""#, "Aé€"),
+            (r#""😀""#, "😀"),
+            ("\"é😀 raw\"", "é😀 raw"),
+        ] {
+            assert!(read(literal).unwrap() == expected);
+        }
+    }
+
+    #[test]
+    fn invalid_strings() {
+        assert!(read(r#""abc"#) == Err(StringError::Unterminated));
+        assert!(read(r#""abc\"#) == Err(StringError::Unterminated));
+        assert!(read(r#""abc\""#) == Err(StringError::Unterminated));
+        assert!(read("\"a\nb\"") == Err(StringError::ControlCharacter(2)));
+
+        for (literal, span) in [
+            (r#""\x""#, 1..3),
+            (r#""ab\u12""#, 3..8),
+            (r#""\u12g4""#, 1..7),
+            // lone surrogates
+            (r#""\ud83d""#, 1..7),
+            (r#""\ud83dx""#, 1..7),
+            (r#""\ude00\ud83d""#, 1..7),
+            (r#""\ud83dA""#, 1..7),
+            // never half a char
+            (r#""\é""#, 1..4),
+            (r#""\u123é""#, 1..8),
+        ] {
+            assert!(read(literal) == Err(StringError::InvalidEscape(span)));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in ["", "temp", "a\"b\\c", "\u{0}\u{1f}\n\r\t\u{7f}", "/é😀"] {
+            assert!(read(&quoted(s)).unwrap() == s);
+        }
+
+        assert!(quoted("a\"\n\u{1}é") == r#""a\"\n\u0001é""#);
+    }
+}
@@ -0,0 +1,245 @@
+use super::PacketDatum;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+// == on PacketDatum is derived, so it's structural: [3] != [[3]]
+// Ord promotes integers to lists, so [3].cmp([[3]]) is Equal.
+
+// HashMap needs Hash to agree with ==, which means Hash has to be structural too.
+// Anyone who wants Ord's equality as their key uses PacketKey instead.
+
+// RefCell doesn't implement Hash, so we walk the children ourselves.
+// The tag byte keeps Integer(0), String("") and List([]) from writing the same bytes.
+
+/// Hashing PacketDatums
+///
+/// Contains:
+/// - Hash for PacketDatum
+///     - structural: agrees with ==, so 3 and [3] hash differently
+/// - PacketKey
+///     - wrapper whose == and Hash agree with Ord, so 3, [3] and [[3]] are the same key
+/// - PacketDatum::fingerprint
+///     - 128 bit structural fingerprint for deduplicating large amounts of packets
+impl Hash for PacketDatum {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            PacketDatum::Integer(i) => {
+                0u8.hash(state);
+                i.hash(state);
+            }
+            PacketDatum::List(l) => {
+                1u8.hash(state);
+                (l.len() as u64).hash(state);
+
+                for packet_datum in l {
+                    packet_datum.borrow().hash(state);
+                }
+            }
+            PacketDatum::String(s) => {
+                2u8.hash(state);
+                s.hash(state);
+            }
+        }
+    }
+}
+
+impl PacketDatum {
+    /// 128 bit structural fingerprint: equal PacketDatums (==) always have equal fingerprints.
+    ///
+    /// Deterministic within a platform, so fingerprints can be compared across runs
+    /// instead of keeping every packet around to dedup.
+    pub fn fingerprint(&self) -> u128 {
+        let mut hasher = Fnv128::new();
+        self.hash(&mut hasher);
+        hasher.finish_128()
+    }
+
+    // Hashes the canonical form under Ord:
+    // anything interchangeable with an atom hashes as that atom,
+    // every other list hashes its length and its children's canonical hashes.
+    fn hash_canonical<H: Hasher>(&self, state: &mut H) {
+        if let Some(atom) = self.promoted_atom() {
+            atom.hash(state);
+            return;
+        }
+
+        if let PacketDatum::List(l) = self {
+            1u8.hash(state);
+            (l.len() as u64).hash(state);
+
+            for packet_datum in l {
+                packet_datum.borrow().hash_canonical(state);
+            }
+        }
+    }
+}
+
+/// PacketKey wraps a PacketDatum so that == and Hash follow Ord instead of structure.
+///
+/// [3], [[3]] and [[[3]]] are all the same key.
+#[derive(Clone)]
+pub struct PacketKey(pub PacketDatum);
+
+impl PartialEq for PacketKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.cmp(&other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for PacketKey {}
+
+impl Hash for PacketKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_canonical(state);
+    }
+}
+
+impl Ord for PacketKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialOrd for PacketKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<PacketDatum> for PacketKey {
+    fn from(packet_datum: PacketDatum) -> Self {
+        PacketKey(packet_datum)
+    }
+}
+
+// FNV-1a over 128 bits: http://www.isthe.com/chongo/tech/comp/fnv/
+// simple, no dependencies and wide enough that collisions across millions of packets are negligible
+struct Fnv128 {
+    state: u128,
+}
+
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Fnv128 {
+            state: Self::OFFSET_BASIS,
+        }
+    }
+
+    fn finish_128(&self) -> u128 {
+        self.state
+    }
+}
+
+impl Hasher for Fnv128 {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u128;
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        (self.state ^ (self.state >> 64)) as u64
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::mutable_key_type)]
+mod tests {
+    use super::PacketKey;
+    use crate::datum::PacketDatum as pd;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{HashMap, HashSet};
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of<T: Hash>(t: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_packets_hash_equal() {
+        let packet_1: pd = "[1,[2,[3,[4,[5,6,7]]]],8,9]".parse().unwrap();
+        let packet_2: pd = "[1,[2,[3,[4,[5,6,7]]]],8,9]".parse().unwrap();
+
+        assert!(hash_of(&packet_1) == hash_of(&packet_2));
+    }
+
+    #[test]
+    fn hash_set_dedups_structurally() {
+        let packets: HashSet<pd> = ["[3]", "[[3]]", "[3]", "[]", "[[]]", "[]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        // [3] and [[3]] are different structures, so both stay
+        assert!(packets.len() == 4);
+    }
+
+    #[test]
+    // [3], [[3]] and [[[3]]] all compare Equal
+    fn packet_key_follows_ord() {
+        let keys: Vec<PacketKey> = ["[3]", "[[3]]", "[[[3]]]"]
+            .iter()
+            .map(|s| PacketKey(s.parse().unwrap()))
+            .collect();
+
+        assert!(keys[0] == keys[1] && keys[1] == keys[2]);
+        assert!(hash_of(&keys[0]) == hash_of(&keys[1]));
+        assert!(hash_of(&keys[1]) == hash_of(&keys[2]));
+
+        let unique: HashSet<PacketKey> = keys.into_iter().collect();
+        assert!(unique.len() == 1);
+
+        let key_1 = PacketKey(r#"["a",1]"#.parse().unwrap());
+        let key_2 = PacketKey(r#"[[["a"]],[1]]"#.parse().unwrap());
+        assert!(key_1 == key_2);
+        assert!(hash_of(&key_1) == hash_of(&key_2));
+    }
+
+    #[test]
+    // [[1],[2,3]] == [1,[[2],3]] under Ord
+    fn packet_key_nested_promotion() {
+        let key_1 = PacketKey("[[1],[2,3]]".parse().unwrap());
+        let key_2 = PacketKey("[1,[[2],3]]".parse().unwrap());
+
+        assert!(key_1 == key_2);
+        assert!(hash_of(&key_1) == hash_of(&key_2));
+
+        let mut counts: HashMap<PacketKey, usize> = HashMap::new();
+        *counts.entry(key_1).or_default() += 1;
+        *counts.entry(key_2).or_default() += 1;
+        assert!(counts.len() == 1);
+    }
+
+    #[test]
+    // [] vs [[]] are not Equal under Ord, the right side has an item
+    fn packet_key_empty_lists_differ() {
+        let key_1 = PacketKey("[]".parse().unwrap());
+        let key_2 = PacketKey("[[]]".parse().unwrap());
+
+        assert!(key_1 != key_2);
+    }
+
+    #[test]
+    fn fingerprint() {
+        let packet_1: pd = "[[1],[2,3,4]]".parse().unwrap();
+        let packet_2: pd = "[[1],[2,3,4]]".parse().unwrap();
+        let packet_3: pd = "[[1],4]".parse().unwrap();
+
+        assert!(packet_1.fingerprint() == packet_2.fingerprint());
+        assert!(packet_1.fingerprint() != packet_3.fingerprint());
+
+        // Integer 0 vs empty list vs empty string
+        assert!(pd::Integer(0).fingerprint() != pd::List(vec![]).fingerprint());
+        assert!(pd::String(String::new()).fingerprint() != pd::List(vec![]).fingerprint());
+        assert!(pd::String(String::new()).fingerprint() != pd::Integer(0).fingerprint());
+        // [3] vs [[3]]
+        let wrapped: pd = "[[3]]".parse().unwrap();
+        assert!(pd::int_list(vec![3]).fingerprint() != wrapped.fingerprint());
+    }
+}
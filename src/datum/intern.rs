@@ -0,0 +1,400 @@
+use super::{PacketDatum, PacketParseError};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// Hash consing: every structurally distinct subtree is built exactly once.
+//
+// The interner interns bottom up. Once a node's children are interned, two nodes are
+// structurally equal exactly when they're the same kind and their children are the same Rcs,
+// so a node's key is just its integer or string, or the addresses of its (already interned) children.
+// Looking that key up either finds the existing node or makes a new one.
+//
+// [[2],[2],[2]] becomes one [2] node shared three times, and every [] in every packet
+// is the same Rc.
+//
+// Interned nodes are shared, so they must never be mutated. They're stored as their own
+// Node type with plain Rc children (no RefCell anywhere), so there's nothing to mutate through:
+// InternedPacket only has read only accessors, and to_datum() gives back an unshared copy.
+// That's also what keeps the address keyed table and Ord's shared subtree shortcut valid.
+
+/// Intern Module:
+/// PacketInterner deduplicates identical subtrees across every packet it interns.
+///
+/// Contains:
+/// - PacketInterner: intern / parse packets, with memory statistics
+/// - InternedPacket: a shared, immutable packet. == and Hash are pointer identity, O(1)
+/// - InternStats: how many nodes were interned vs how many were actually allocated
+#[derive(Default)]
+pub struct PacketInterner {
+    nodes: HashMap<NodeKey, Rc<Node>>,
+    stats: InternStats,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum NodeKey {
+    Integer(i32),
+    String(String),
+    // addresses of the interned children
+    List(Vec<usize>),
+}
+
+// an interned node, immutable once built
+enum Node {
+    Integer(i32),
+    String(Box<str>),
+    List(Vec<Rc<Node>>),
+}
+
+/// Memory statistics of a PacketInterner.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct InternStats {
+    /// nodes (lists and atoms) of every packet interned, what building them separately costs
+    pub nodes_seen: usize,
+    /// nodes the interner actually allocated
+    pub unique_nodes: usize,
+}
+
+impl InternStats {
+    /// nodes_seen / unique_nodes: 1.0 means nothing was shared
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_nodes == 0 {
+            return 1.0;
+        }
+
+        self.nodes_seen as f64 / self.unique_nodes as f64
+    }
+
+    /// Rough bytes saved by sharing: one Rc<RefCell<PacketDatum>> allocation per shared node.
+    /// (List child buffers are saved as well, so the real savings are higher.)
+    pub fn bytes_saved(&self) -> usize {
+        (self.nodes_seen - self.unique_nodes) * NODE_BYTES
+    }
+}
+
+// Rc strong + weak counts, RefCell borrow flag, PacketDatum: what each packet would allocate per node
+const NODE_BYTES: usize =
+    2 * std::mem::size_of::<usize>() + std::mem::size_of::<RefCell<PacketDatum>>();
+
+impl PacketInterner {
+    pub fn new() -> Self {
+        PacketInterner::default()
+    }
+
+    pub fn intern(&mut self, packet_datum: &PacketDatum) -> InternedPacket {
+        InternedPacket(self.intern_node(packet_datum))
+    }
+
+    pub fn parse(&mut self, s: &str) -> Result<InternedPacket, PacketParseError> {
+        let packet_datum: PacketDatum = s.parse()?;
+
+        Ok(self.intern(&packet_datum))
+    }
+
+    pub fn stats(&self) -> InternStats {
+        self.stats
+    }
+
+    fn intern_node(&mut self, packet_datum: &PacketDatum) -> Rc<Node> {
+        self.stats.nodes_seen += 1;
+
+        let (key, node) = match packet_datum {
+            PacketDatum::Integer(i) => (NodeKey::Integer(*i), Node::Integer(*i)),
+            PacketDatum::String(s) => (NodeKey::String(s.clone()), Node::String(s.as_str().into())),
+            PacketDatum::List(l) => {
+                let children: Vec<Rc<Node>> = l
+                    .iter()
+                    .map(|child| self.intern_node(&child.borrow()))
+                    .collect();
+
+                let key = NodeKey::List(children.iter().map(address).collect());
+
+                (key, Node::List(children))
+            }
+        };
+
+        let stats = &mut self.stats;
+
+        Rc::clone(self.nodes.entry(key).or_insert_with(|| {
+            stats.unique_nodes += 1;
+            Rc::new(node)
+        }))
+    }
+}
+
+fn address(node: &Rc<Node>) -> usize {
+    Rc::as_ptr(node) as usize
+}
+
+/// A packet built by a PacketInterner.
+///
+/// Two InternedPackets from the same interner are == exactly when they're structurally equal,
+/// and checking that is a pointer comparison. Ord is the usual Day 13 ordering,
+/// but any shared subtree is known to be Equal without walking it.
+///
+/// The nodes are shared with every other packet from the same interner, so there's only read
+/// access: is_list, as_integer, as_str and children (which are InternedPackets themselves).
+/// Nothing hands out mutable access to a shared node, so changing the string both ["a"]s
+/// point at doesn't compile (reading it with to_ascii_uppercase instead would):
+///
+/// ```compile_fail,E0596
+/// use packet_13_22::datum::PacketInterner;
+///
+/// let mut interner = PacketInterner::new();
+/// let packet = interner.parse(r#"[["a"],["a"]]"#).unwrap();
+/// let first = packet.children().next().unwrap();
+/// let string = first.children().next().unwrap();
+///
+/// string.as_str().unwrap().make_ascii_uppercase();
+/// ```
+#[derive(Clone)]
+pub struct InternedPacket(Rc<Node>);
+
+impl InternedPacket {
+    pub fn is_list(&self) -> bool {
+        matches!(*self.0, Node::List(_))
+    }
+
+    /// Some(integer) if this is an integer, None if it's a list or a string.
+    pub fn as_integer(&self) -> Option<i32> {
+        match *self.0 {
+            Node::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Some(string) if this is a string, None otherwise.
+    pub fn as_str(&self) -> Option<&str> {
+        match &*self.0 {
+            Node::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The items of this list, each one still shared. An atom has no children.
+    pub fn children(&self) -> impl ExactSizeIterator<Item = InternedPacket> + '_ {
+        let children: &[Rc<Node>] = match &*self.0 {
+            Node::List(l) => l,
+            _ => &[],
+        };
+
+        children
+            .iter()
+            .map(|child| InternedPacket(Rc::clone(child)))
+    }
+
+    /// An owned copy that doesn't share any nodes, so it's safe to mutate.
+    pub fn to_datum(&self) -> PacketDatum {
+        match &*self.0 {
+            Node::Integer(i) => PacketDatum::Integer(*i),
+            Node::String(s) => PacketDatum::String(s.to_string()),
+            Node::List(_) => PacketDatum::list(
+                self.children()
+                    .map(|child| Rc::new(RefCell::new(child.to_datum())))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl PartialEq for InternedPacket {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for InternedPacket {}
+
+impl Hash for InternedPacket {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        address(&self.0).hash(state);
+    }
+}
+
+impl Ord for InternedPacket {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_shared(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for InternedPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Day 13 ordering that skips any pair of children that are the same node
+fn cmp_shared(left: &Node, right: &Node) -> Ordering {
+    if std::ptr::eq(left, right) {
+        return Ordering::Equal;
+    }
+
+    match (left, right) {
+        (Node::List(l1), Node::List(l2)) => {
+            for (child_1, child_2) in l1.iter().zip(l2) {
+                let ordering = cmp_shared(child_1, child_2);
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+
+            l1.len().cmp(&l2.len())
+        }
+        (Node::List(l), atom) => cmp_atom_with_list(atom, l).reverse(),
+        (atom, Node::List(l)) => cmp_atom_with_list(atom, l),
+        (Node::Integer(i1), Node::Integer(i2)) => i1.cmp(i2),
+        (Node::String(s1), Node::String(s2)) => s1.cmp(s2),
+        (Node::Integer(_), Node::String(_)) => Ordering::Less,
+        (Node::String(_), Node::Integer(_)) => Ordering::Greater,
+    }
+}
+
+// the atom is promoted to [atom]
+fn cmp_atom_with_list(atom: &Node, l: &[Rc<Node>]) -> Ordering {
+    match l.first() {
+        Some(first) => cmp_shared(atom, first).then(1.cmp(&l.len())),
+        None => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    // [[2],[2],[2]]: one [2] node shared three times
+    fn shares_subtrees() {
+        let mut interner = PacketInterner::new();
+        let packet = interner.parse("[[2],[2],[2]]").unwrap();
+
+        let children: Vec<InternedPacket> = packet.children().collect();
+        assert!(children.len() == 3);
+        assert!(children[0] == children[1] && children[1] == children[2]);
+
+        // 1 outer list + 3 * ([2] + 2)
+        assert!(interner.stats().nodes_seen == 7);
+        // outer list, [2], 2
+        assert!(interner.stats().unique_nodes == 3);
+    }
+
+    #[test]
+    fn equal_packets_are_the_same_node() {
+        let mut interner = PacketInterner::new();
+        let packet_1 = interner.parse("[1,[2,[3,[4,[5,6,7]]]],8,9]").unwrap();
+        let packet_2 = interner
+            .parse("[1, [2, [3, [4, [5, 6, 7]]]], 8, 9]")
+            .unwrap();
+        let packet_3 = interner.parse("[1,[2,[3,[4,[5,6,0]]]],8,9]").unwrap();
+
+        assert!(packet_1 == packet_2);
+        assert!(packet_1 != packet_3);
+
+        let unique: HashSet<InternedPacket> = [packet_1, packet_2, packet_3].into_iter().collect();
+        assert!(unique.len() == 2);
+    }
+
+    #[test]
+    // pointer identity is structural: [3] and [[3]] compare Equal but aren't ==
+    fn eq_is_structural() {
+        let mut interner = PacketInterner::new();
+        let three = interner.parse("[3]").unwrap();
+        let nested_three = interner.parse("[[3]]").unwrap();
+
+        assert!(three != nested_three);
+        assert!(three.cmp(&nested_three) == Ordering::Equal);
+    }
+
+    #[test]
+    fn ord_matches_packet_datum() {
+        let packets = [
+            "[1,1,3,1,1]",
+            "[1,1,5,1,1]",
+            "[[1],[2,3,4]]",
+            "[[1],4]",
+            "[9]",
+            "[[8,7,6]]",
+            "[[4,4],4,4]",
+            "[[4,4],4,4,4]",
+            "[]",
+            "[3]",
+            "[[[]]]",
+            "[[]]",
+            r#"["a",1]"#,
+            r#"[["a"],"b"]"#,
+        ];
+
+        let mut interner = PacketInterner::new();
+
+        for s1 in packets {
+            for s2 in packets {
+                let (packet_datum_1, packet_datum_2): (PacketDatum, PacketDatum) =
+                    (s1.parse().unwrap(), s2.parse().unwrap());
+                let expected = packet_datum_1.cmp(&packet_datum_2);
+
+                let (packet_1, packet_2) =
+                    (interner.parse(s1).unwrap(), interner.parse(s2).unwrap());
+                assert!(packet_1.cmp(&packet_2) == expected);
+            }
+        }
+    }
+
+    #[test]
+    fn to_datum_is_unshared() {
+        let mut interner = PacketInterner::new();
+        let packet = interner.parse("[[2],[2]]").unwrap();
+
+        let copy = packet.to_datum();
+        assert!(copy == "[[2],[2]]".parse().unwrap());
+
+        if let PacketDatum::List(l) = &copy {
+            assert!(!Rc::ptr_eq(&l[0], &l[1]));
+            l[0].borrow_mut().add_list(PacketDatum::rc_int(5));
+        }
+
+        // the interned packet didn't change
+        assert!(packet.to_datum() == "[[2],[2]]".parse().unwrap());
+    }
+
+    #[test]
+    // the only ways into an interned packet are read only or copies
+    fn shared_nodes_stay_unchanged() {
+        let mut interner = PacketInterner::new();
+        let packet_1 = interner.parse(r#"[[2],[2],"a"]"#).unwrap();
+        let packet_2 = interner.parse("[[2]]").unwrap();
+
+        let two = packet_1.children().next().unwrap();
+        assert!(two.is_list());
+        assert!(two.children().next().unwrap().as_integer() == Some(2));
+        assert!(packet_1.children().nth(2).unwrap().as_str() == Some("a"));
+        assert!(packet_1.as_integer().is_none() && packet_1.as_str().is_none());
+
+        // mutating a copy of the shared [2] leaves both packets alone
+        let mut copy = two.to_datum();
+        copy.add_list(PacketDatum::rc_int(9));
+        assert!(copy == "[2,9]".parse().unwrap());
+
+        assert!(packet_1.to_datum() == r#"[[2],[2],"a"]"#.parse().unwrap());
+        assert!(packet_2.to_datum() == "[[2]]".parse().unwrap());
+        assert!(packet_2.children().next().unwrap() == two);
+        assert!(interner.parse("[2]").unwrap() == two);
+    }
+
+    #[test]
+    fn stats() {
+        let mut interner = PacketInterner::new();
+
+        for _ in 0..100 {
+            interner.parse("[[2],[],[6]]").unwrap();
+        }
+
+        let stats = interner.stats();
+        assert!(stats.nodes_seen == 600);
+        assert!(stats.unique_nodes == 6);
+        assert!(stats.dedup_ratio() == 100.0);
+        assert!(stats.bytes_saved() > 0);
+    }
+}
@@ -0,0 +1,388 @@
+use super::escape::{scan_string, unescape};
+use super::{PacketDatum, PacketParseError};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// A packet is JSON already: an array whose items are arrays, integers or strings
+// (packet strings use JSON's quotes and escapes).
+// from_str is looser than JSON though (whitespace separates integers, commas can go anywhere),
+// and JSON is looser than a packet (objects, floats, booleans ...).
+//
+// from_json_str is the parser for text that claims to be JSON: exactly the JSON grammar,
+// restricted to arrays, integers and strings.
+//  - whitespace is JSON whitespace: space, \t, \n and \r, and only between tokens
+//  - commas go between items, no leading, doubled or trailing commas
+//  - numbers are JSON integers: -?(0|[1-9][0-9]*), no leading zeros, no +,
+//    no fraction and no exponent (1e2 is a float in JSON), and they have to fit in an i32
+//  - strings are JSON strings. A \u escape of a lone surrogate is rejected,
+//    since a packet string has to be valid Unicode
+//
+// With the serde_json feature, serde_json::Value converts both ways. Values that aren't
+// arrays, integers or strings are rejected with the path (array indices from the root)
+// to the first one.
+
+impl PacketDatum {
+    /// Parses a JSON array of integers and strings (and arrays of them ...) into a List PacketDatum.
+    ///
+    /// Stricter than from_str: only text that's valid JSON is accepted.
+    pub fn from_json_str(s: &str) -> Result<PacketDatum, PacketParseError> {
+        let bytes = s.as_bytes();
+        let mut pos = skip_whitespace(bytes, 0);
+
+        if bytes.get(pos) != Some(&b'[') {
+            return Err(PacketParseError);
+        }
+
+        let mut open_lists: Vec<Vec<Rc<RefCell<PacketDatum>>>> = vec![];
+        // what can come next: an item (or a ] right after a [), or a , or ] after an item
+        let mut after_open = false;
+        let mut after_item = false;
+
+        let root = loop {
+            pos = skip_whitespace(bytes, pos);
+
+            let item = match bytes.get(pos) {
+                Some(b'[') if !after_item => {
+                    open_lists.push(vec![]);
+                    after_open = true;
+                    pos += 1;
+                    continue;
+                }
+                Some(b']') if after_open || after_item => {
+                    pos += 1;
+                    PacketDatum::List(open_lists.pop().expect("a list is open"))
+                }
+                Some(b',') if after_item => {
+                    after_item = false;
+                    pos += 1;
+                    continue;
+                }
+                Some(b'-' | b'0'..=b'9') if !after_item => {
+                    let (i, end) = json_integer(bytes, pos)?;
+                    pos = end;
+                    PacketDatum::Integer(i)
+                }
+                Some(b'"') if !after_item => {
+                    let end = scan_string(s, pos).map_err(|_| PacketParseError)?;
+                    let string = unescape(&s[pos + 1..end - 1]);
+                    pos = end;
+                    PacketDatum::String(string)
+                }
+                _ => return Err(PacketParseError),
+            };
+
+            after_open = false;
+            after_item = true;
+
+            match open_lists.last_mut() {
+                Some(children) => children.push(Rc::new(RefCell::new(item))),
+                None => break item,
+            }
+        };
+
+        match skip_whitespace(bytes, pos) == bytes.len() {
+            true => Ok(root),
+            false => Err(PacketParseError),
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while let Some(b' ' | b'\t' | b'\n' | b'\r') = bytes.get(pos) {
+        pos += 1;
+    }
+
+    pos
+}
+
+// -?(0|[1-9][0-9]*) starting at pos, and the position after it
+fn json_integer(bytes: &[u8], mut pos: usize) -> Result<(i32, usize), PacketParseError> {
+    let negative = bytes[pos] == b'-';
+    if negative {
+        pos += 1;
+    }
+
+    let digits = bytes[pos..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+
+    let valid = match bytes.get(pos) {
+        Some(b'0') => digits == 1,
+        Some(b'1'..=b'9') => true,
+        _ => false,
+    };
+
+    // a fraction or an exponent makes it a float
+    if !valid || matches!(bytes.get(pos + digits), Some(b'.' | b'e' | b'E')) {
+        return Err(PacketParseError);
+    }
+
+    // accumulated as a negative number so i32::MIN fits
+    let mut n: i32 = 0;
+    for c in &bytes[pos..pos + digits] {
+        n = n
+            .checked_mul(10)
+            .and_then(|n| n.checked_sub((c - b'0') as i32))
+            .ok_or(PacketParseError)?;
+    }
+
+    let n = if negative {
+        n
+    } else {
+        n.checked_neg().ok_or(PacketParseError)?
+    };
+
+    Ok((n, pos + digits))
+}
+
+#[cfg(feature = "serde_json")]
+pub use value::JsonValueError;
+
+#[cfg(feature = "serde_json")]
+mod value {
+    use super::PacketDatum;
+    use serde_json::Value;
+    use std::cell::RefCell;
+    use std::fmt;
+    use std::rc::Rc;
+
+    /// Why a serde_json::Value isn't a packet.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum JsonValueError {
+        /// The root value isn't an array.
+        NotAList { found: &'static str },
+        /// The value at this path (array indices from the root) isn't an array, an i32 or a string.
+        Unsupported {
+            path: Vec<usize>,
+            found: &'static str,
+        },
+    }
+
+    impl fmt::Display for JsonValueError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                JsonValueError::NotAList { found } => {
+                    write!(f, "a packet has to be an array, found {}", found)
+                }
+                JsonValueError::Unsupported { path, found } => write!(
+                    f,
+                    "value at {:?} is {}, expected an array, an integer or a string",
+                    path, found
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for JsonValueError {}
+
+    // what the value is, for errors
+    fn describe(value: &Value) -> &'static str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::String(_) => "a string",
+            Value::Object(_) => "an object",
+            Value::Array(_) => "an array",
+            Value::Number(n) if n.is_f64() => "a float",
+            Value::Number(n) if n.as_i64().and_then(|n| i32::try_from(n).ok()).is_some() => {
+                "an integer"
+            }
+            Value::Number(_) => "an integer that doesn't fit in an i32",
+        }
+    }
+
+    fn to_packet_datum(
+        value: &Value,
+        path: &mut Vec<usize>,
+    ) -> Result<PacketDatum, JsonValueError> {
+        match value {
+            Value::Array(items) => {
+                let mut children = Vec::with_capacity(items.len());
+
+                for (idx, item) in items.iter().enumerate() {
+                    path.push(idx);
+                    children.push(Rc::new(RefCell::new(to_packet_datum(item, path)?)));
+                    path.pop();
+                }
+
+                Ok(PacketDatum::List(children))
+            }
+            Value::Number(n) => n
+                .as_i64()
+                .and_then(|n| i32::try_from(n).ok())
+                .map(PacketDatum::Integer)
+                .ok_or_else(|| JsonValueError::Unsupported {
+                    path: path.clone(),
+                    found: describe(value),
+                }),
+            Value::String(s) => Ok(PacketDatum::String(s.clone())),
+            _ => Err(JsonValueError::Unsupported {
+                path: path.clone(),
+                found: describe(value),
+            }),
+        }
+    }
+
+    impl TryFrom<&Value> for PacketDatum {
+        type Error = JsonValueError;
+
+        fn try_from(value: &Value) -> Result<Self, Self::Error> {
+            if !value.is_array() {
+                return Err(JsonValueError::NotAList {
+                    found: describe(value),
+                });
+            }
+
+            to_packet_datum(value, &mut vec![])
+        }
+    }
+
+    impl TryFrom<Value> for PacketDatum {
+        type Error = JsonValueError;
+
+        fn try_from(value: Value) -> Result<Self, Self::Error> {
+            PacketDatum::try_from(&value)
+        }
+    }
+
+    impl From<&PacketDatum> for Value {
+        fn from(packet_datum: &PacketDatum) -> Self {
+            match packet_datum {
+                PacketDatum::Integer(i) => Value::from(*i),
+                PacketDatum::String(s) => Value::from(s.as_str()),
+                PacketDatum::List(l) => Value::Array(
+                    l.iter()
+                        .map(|child| Value::from(&*child.borrow()))
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    impl From<PacketDatum> for Value {
+        fn from(packet_datum: PacketDatum) -> Self {
+            Value::from(&packet_datum)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datum::tests::parse;
+    use crate::datum::PacketDatum;
+
+    #[test]
+    fn valid_json() {
+        for (json, packet) in [
+            ("[]", "[]"),
+            ("[1,[2,[3,[4,[5,6,7]]]],8,9]", "[1,[2,[3,[4,[5,6,7]]]],8,9]"),
+            (" \t\r\n[ 1 ,\n  [ ] , -0 ]\n", "[1,[],0]"),
+            ("[-2147483648,2147483647]", "[-2147483648,2147483647]"),
+            ("[[[]],0,10]", "[[[]],0,10]"),
+            (
+                r#"["temp", [1,"\u00e9\ud83d\ude00\/"]]"#,
+                r#"["temp",[1,"é😀/"]]"#,
+            ),
+        ] {
+            assert!(PacketDatum::from_json_str(json).unwrap() == parse(packet));
+        }
+    }
+
+    #[test]
+    // all of these are fine for from_str, or valid JSON that isn't a packet
+    fn invalid_json() {
+        for json in [
+            "",
+            "1",
+            "[1 2]",
+            "[,1]",
+            "[1,,2]",
+            "[1,]",
+            "[1][2]",
+            "[01]",
+            "[-01]",
+            "[+1]",
+            "[1.0]",
+            "[1e2]",
+            "[1E2]",
+            "[-]",
+            "[2147483648]",
+            "[\"1\" 2]",
+            "[\"1]",
+            "[\"\\x\"]",
+            "[\"\\ud83d\"]",
+            "[\"\t\"]",
+            "[null]",
+            "[1\u{b}]",
+            "[1]]",
+            "[[1]",
+        ] {
+            assert!(PacketDatum::from_json_str(json).is_err());
+        }
+    }
+
+    #[test]
+    // the parser doesn't recurse, so there's no depth limit like serde_json's 128
+    fn deep_nesting() {
+        let json = format!("{}{}", "[".repeat(1000), "]".repeat(1000));
+
+        assert!(PacketDatum::from_json_str(&json).is_ok());
+    }
+
+    #[cfg(feature = "serde_json")]
+    mod value {
+        use super::parse;
+        use crate::datum::{JsonValueError, PacketDatum};
+        use serde_json::{json, Value};
+
+        #[test]
+        fn round_trip() {
+            let packet = parse(r#"[1,[2,[3,[4,[5,6,-7]]]],[],"a\"b",9]"#);
+
+            let value = Value::from(&packet);
+
+            assert!(value == json!([1, [2, [3, [4, [5, 6, -7]]]], [], "a\"b", 9]));
+            assert!(serde_json::to_string(&value).unwrap() == packet.to_string());
+            assert!(PacketDatum::try_from(value).unwrap() == packet);
+        }
+
+        #[test]
+        fn rejected_values() {
+            for (value, path, found) in [
+                (json!([1, {"a": 1}]), vec![1], "an object"),
+                (json!([[1, ["2", false]]]), vec![0, 1, 1], "a boolean"),
+                (json!([1.5]), vec![0], "a float"),
+                (json!([[true]]), vec![0, 0], "a boolean"),
+                (json!([null]), vec![0], "null"),
+                (
+                    json!([3_000_000_000u64]),
+                    vec![0],
+                    "an integer that doesn't fit in an i32",
+                ),
+            ] {
+                assert!(
+                    PacketDatum::try_from(&value)
+                        == Err(JsonValueError::Unsupported { path, found })
+                );
+            }
+
+            assert!(
+                PacketDatum::try_from(json!(1))
+                    == Err(JsonValueError::NotAList {
+                        found: "an integer"
+                    })
+            );
+        }
+
+        #[test]
+        fn error_message() {
+            let error = PacketDatum::try_from(json!([1, [2, {}]])).unwrap_err();
+
+            assert!(
+                error.to_string()
+                    == "value at [1, 1] is an object, expected an array, an integer or a string"
+            );
+        }
+    }
+}
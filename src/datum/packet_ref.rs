@@ -0,0 +1,381 @@
+use super::escape::{scan_string, unescape};
+use super::text::compare_atoms;
+use super::{validate_str, PacketDatum, PacketParseError, Token};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+// A PacketRef is a window into the packet text: either a whole list "[...]", a single integer
+// or a single string (quotes included).
+//
+// The text is validated once when the PacketRef is made, after that every bracket is known to
+// have a partner, so walking the children is just:
+//  - skip commas and whitespace
+//  - [ : scan forward counting brackets until its partner closes, that's one child
+//    (strings are skipped whole, the brackets inside them don't count)
+//  - an integer: scan the digits, that's one child
+//  - a string: scan to its closing quote, that's one child
+//  - ] : the list is done
+//
+// Nothing is parsed until it's asked for, so peeking at the first item of a huge packet
+// only reads as far as that item.
+
+/// PacketRef:
+/// Borrowed, lazily parsed view of a packet string.
+///
+/// Compares with the same rules as Ord for PacketDatum,
+/// and to_datum() builds the owned PacketDatum when it's needed.
+#[derive(Clone, Copy)]
+pub struct PacketRef<'a> {
+    text: &'a str,
+}
+
+impl<'a> PacketRef<'a> {
+    /// Validates the whole packet once. Same rules as compare_str.
+    pub fn new(s: &'a str) -> Result<Self, PacketParseError> {
+        validate_str(s)?;
+
+        // separators around the packet are allowed, but aren't part of it
+        Ok(PacketRef {
+            text: s.trim_matches(|c: char| c == ',' || c.is_whitespace()),
+        })
+    }
+
+    /// The text this PacketRef covers, ex: "[2,3]", "4" or "\"a\"" (still escaped).
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    pub fn is_list(&self) -> bool {
+        self.text.starts_with('[')
+    }
+
+    pub fn is_string(&self) -> bool {
+        self.text.starts_with('"')
+    }
+
+    /// Some(integer) if this is an integer, None if it's a list or a string.
+    pub fn as_integer(&self) -> Option<i32> {
+        if self.is_list() || self.is_string() {
+            return None;
+        }
+
+        // validated, so it's a well formed i32
+        self.text.parse().ok()
+    }
+
+    /// Some(string) with the escapes replaced if this is a string, None otherwise.
+    pub fn as_string(&self) -> Option<String> {
+        self.string_content().map(unescape)
+    }
+
+    // what's between the quotes
+    fn string_content(&self) -> Option<&'a str> {
+        match self.is_string() {
+            true => Some(&self.text[1..self.text.len() - 1]),
+            false => None,
+        }
+    }
+
+    fn atom(&self) -> Option<Token<'a>> {
+        match (self.as_integer(), self.string_content()) {
+            (Some(i), _) => Some(Token::Integer(i)),
+            (_, Some(content)) => Some(Token::String(content)),
+            (None, None) => None,
+        }
+    }
+
+    /// The items of this list, parsed one at a time. An atom has no children.
+    pub fn children(&self) -> Children<'a> {
+        let pos = if self.is_list() { 1 } else { self.text.len() };
+
+        Children {
+            text: self.text,
+            pos,
+        }
+    }
+
+    pub fn first(&self) -> Option<PacketRef<'a>> {
+        self.children().next()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<PacketRef<'a>> {
+        self.children().nth(idx)
+    }
+
+    /// Builds the owned PacketDatum for this view.
+    pub fn to_datum(&self) -> PacketDatum {
+        if let Some(i) = self.as_integer() {
+            return PacketDatum::Integer(i);
+        }
+
+        if let Some(s) = self.as_string() {
+            return PacketDatum::String(s);
+        }
+
+        PacketDatum::List(
+            self.children()
+                .map(|child| Rc::new(RefCell::new(child.to_datum())))
+                .collect(),
+        )
+    }
+}
+
+/// Iterator over the items of a PacketRef list.
+pub struct Children<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = PacketRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.text.as_bytes();
+
+        while self.pos < bytes.len()
+            && (bytes[self.pos] == b',' || bytes[self.pos].is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+
+        let start = self.pos;
+
+        match bytes.get(start)? {
+            // pos stays on the ] so the iterator keeps returning None
+            b']' => None,
+            b'[' => {
+                let mut depth = 0;
+
+                loop {
+                    match bytes[self.pos] {
+                        b'[' => depth += 1,
+                        b']' => depth -= 1,
+                        b'"' => {
+                            self.pos = scan_string(self.text, self.pos).expect("validated");
+                            continue;
+                        }
+                        _ => (),
+                    }
+
+                    self.pos += 1;
+
+                    if depth == 0 {
+                        break;
+                    }
+                }
+
+                Some(PacketRef {
+                    text: &self.text[start..self.pos],
+                })
+            }
+            b'"' => {
+                self.pos = scan_string(self.text, start).expect("validated");
+
+                Some(PacketRef {
+                    text: &self.text[start..self.pos],
+                })
+            }
+            _ => {
+                self.pos += 1;
+
+                while self.pos < bytes.len() && bytes[self.pos].is_ascii_digit() {
+                    self.pos += 1;
+                }
+
+                Some(PacketRef {
+                    text: &self.text[start..self.pos],
+                })
+            }
+        }
+    }
+}
+
+// Same rules as Ord for PacketDatum: an atom compared with a list is promoted to [atom]
+impl Ord for PacketRef<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.atom(), other.atom()) {
+            (Some(atom_1), Some(atom_2)) => compare_atoms(atom_1, atom_2),
+            (None, None) => {
+                let mut children_1 = self.children();
+                let mut children_2 = other.children();
+
+                loop {
+                    match (children_1.next(), children_2.next()) {
+                        (None, None) => return Ordering::Equal,
+                        (None, Some(_)) => return Ordering::Less,
+                        (Some(_), None) => return Ordering::Greater,
+                        (Some(child_1), Some(child_2)) => {
+                            let ordering = child_1.cmp(&child_2);
+
+                            if ordering != Ordering::Equal {
+                                return ordering;
+                            }
+                        }
+                    }
+                }
+            }
+            (Some(_), None) => cmp_atom_with_list(self, other),
+            (None, Some(_)) => cmp_atom_with_list(other, self).reverse(),
+        }
+    }
+}
+
+// [atom] vs list
+fn cmp_atom_with_list(atom: &PacketRef, list: &PacketRef) -> Ordering {
+    let mut children = list.children();
+
+    match children.next() {
+        None => Ordering::Greater,
+        Some(first) => atom.cmp(&first).then(match children.next() {
+            None => Ordering::Equal,
+            Some(_) => Ordering::Less,
+        }),
+    }
+}
+
+impl PartialOrd for PacketRef<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Equal means cmp is Equal, so [3] == [[3]] for PacketRefs
+impl PartialEq for PacketRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PacketRef<'_> {}
+
+impl fmt::Display for PacketRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketRef;
+    use crate::datum::tests::EXAMPLE_PACKETS;
+    use crate::datum::PacketDatum;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn lazy_children() {
+        let packet = PacketRef::new("[1,[2,[3,[4,[5,6,7]]]],8,9]").unwrap();
+
+        assert!(packet.first().unwrap().as_integer() == Some(1));
+        assert!(packet.get(1).unwrap().as_str() == "[2,[3,[4,[5,6,7]]]]");
+        assert!(packet.get(3).unwrap().as_integer() == Some(9));
+        assert!(packet.get(4).is_none());
+        assert!(packet.children().count() == 4);
+
+        let nested = packet.get(1).unwrap().get(1).unwrap();
+        assert!(nested.to_string() == "[3,[4,[5,6,7]]]");
+        assert!(nested.first().unwrap().first().is_none());
+    }
+
+    #[test]
+    fn whitespace_and_negatives() {
+        let packet = PacketRef::new(" [ -1 , [ 2 ,-30 ] ]\n").unwrap();
+
+        assert!(packet.first().unwrap().as_integer() == Some(-1));
+        assert!(packet.get(1).unwrap().get(1).unwrap().as_integer() == Some(-30));
+        assert!(packet.to_datum() == "[-1,[2,-30]]".parse().unwrap());
+    }
+
+    #[test]
+    // found by the compare fuzz target: ",[8,9]" was treated as an integer
+    fn separators_around_the_packet() {
+        let packet = PacketRef::new(",[8,9]\n").unwrap();
+
+        assert!(packet.as_str() == "[8,9]");
+        assert!(packet.is_list());
+        assert!(packet.to_datum() == "[8,9]".parse().unwrap());
+        assert!(packet > PacketRef::new("\r[\r]").unwrap());
+    }
+
+    #[test]
+    // brackets and commas inside strings are just text
+    fn strings() {
+        let packet = PacketRef::new(r#"[1,"a]\"[",[["b,"]],"😀"]"#).unwrap();
+
+        assert!(packet.children().count() == 4);
+        assert!(packet.get(1).unwrap().as_str() == r#""a]\"[""#);
+        assert!(packet.get(1).unwrap().as_string().unwrap() == "a]\"[");
+        assert!(packet.get(1).unwrap().as_integer().is_none());
+        assert!(packet.get(2).unwrap().as_str() == r#"[["b,"]]"#);
+        assert!(packet.get(3).unwrap().is_string());
+        assert!(packet.first().unwrap().as_string().is_none());
+        assert!(packet.to_datum() == packet.as_str().parse().unwrap());
+
+        let strings = [
+            r#"["a"]"#,
+            r#"[[["a"]]]"#,
+            r#"["b"]"#,
+            r#"[1,"a"]"#,
+            r#"["a",1]"#,
+            "[[]]",
+        ];
+        for s1 in strings {
+            for s2 in strings {
+                let (packet_datum_1, packet_datum_2): (PacketDatum, PacketDatum) =
+                    (s1.parse().unwrap(), s2.parse().unwrap());
+                let ordering = PacketRef::new(s1)
+                    .unwrap()
+                    .cmp(&PacketRef::new(s2).unwrap());
+
+                assert!(ordering == packet_datum_1.cmp(&packet_datum_2));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for invalid in ["", "1", "[1,2", "[1,2]]", "[1,2][3]", "[1,a]", "]"] {
+            assert!(PacketRef::new(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn matches_packet_datum() {
+        for s1 in EXAMPLE_PACKETS {
+            let packet_ref_1 = PacketRef::new(s1).unwrap();
+            let packet_datum_1: PacketDatum = s1.parse().unwrap();
+
+            assert!(packet_ref_1.to_datum() == packet_datum_1);
+
+            for s2 in EXAMPLE_PACKETS {
+                let packet_ref_2 = PacketRef::new(s2).unwrap();
+                let packet_datum_2: PacketDatum = s2.parse().unwrap();
+
+                assert!(packet_ref_1.cmp(&packet_ref_2) == packet_datum_1.cmp(&packet_datum_2));
+            }
+        }
+    }
+
+    #[test]
+    // [3] == [[3]] == [[[3]]] but [3] < [[3,0]]
+    fn integer_promotion() {
+        let three = PacketRef::new("[3]").unwrap();
+
+        assert!(three == PacketRef::new("[[[3]]]").unwrap());
+        assert!(three.cmp(&PacketRef::new("[[3,0]]").unwrap()) == Ordering::Less);
+        assert!(three.cmp(&PacketRef::new("[[]]").unwrap()) == Ordering::Greater);
+    }
+
+    #[test]
+    fn sort() {
+        let mut packets: Vec<PacketRef> = EXAMPLE_PACKETS
+            .iter()
+            .map(|s| PacketRef::new(s).unwrap())
+            .collect();
+        packets.sort();
+
+        let position = |s| packets.iter().position(|p| p.as_str() == s).unwrap() + 1;
+        assert!(position("[[2]]") * position("[[6]]") == 140);
+    }
+}
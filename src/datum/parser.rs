@@ -1,4 +1,5 @@
-use super::PacketDatum;
+use super::escape::unescape;
+use super::{PacketDatum, Token, Tokens};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -75,6 +76,15 @@ use std::str::FromStr;
 
 // This should get us the "Tree Hierarchy" where the root PacketDatum List is in the hashmap @ index 0
 
+// Strings are added to their parent list like ints. A string token runs from its " to the
+// closing " (escapes and all, see escape.rs), so brackets and commas inside it are just text.
+
+// The tokens come from the same cursor compare_str and validate_str read (text.rs),
+// so all three accept exactly the same strings:
+// - commas and whitespace separate tokens: "[1 2]" is [1,2]
+// - any other character outside a string is an error, not skipped
+// - so is anything after the root list closes: "[1][2]" is an error, not [1]
+
 #[derive(Debug)]
 pub struct PacketParseError;
 
@@ -85,17 +95,14 @@ impl FromStr for PacketDatum {
         let mut open_bracket_indices: Vec<usize> = Vec::new();
         let mut lists: HashMap<usize, Rc<RefCell<PacketDatum>>> = HashMap::new();
 
-        if s.is_empty() {
-            return Err(PacketParseError);
-        }
-
-        if s.chars().next().unwrap() != '[' {
-            return Err(PacketParseError);
-        }
+        // Tokens rejects anything that isn't a bracket, an integer, a string or a separator,
+        // atoms outside the root list, a ] with nothing to close and text after the root closes
+        let mut tokens = Tokens::new(s);
+        let mut idx = 0;
 
-        for (idx, token) in get_valid_tokens(s).iter().enumerate() {
-            match token.as_str() {
-                "[" => {
+        while let Some(token) = tokens.next_token()? {
+            match token {
+                Token::Open => {
                     let new_list = Rc::new(RefCell::new(PacketDatum::List(vec![])));
 
                     if let Some(parent_bracket_idx) = open_bracket_indices.last() {
@@ -107,76 +114,145 @@ impl FromStr for PacketDatum {
 
                     open_bracket_indices.push(idx);
                 }
-                "]" => {
-                    if open_bracket_indices.is_empty() {
-                        return Err(PacketParseError);
-                    }
-
+                Token::Close => {
                     open_bracket_indices.pop();
                 }
-                token => match open_bracket_indices.last() {
-                    Some(parent_bracket_idx) => {
-                        let i: i32 = token.parse().unwrap();
-                        let i = Rc::new(RefCell::new(PacketDatum::Integer(i)));
-                        let parent_list = lists.get(parent_bracket_idx).unwrap();
-                        parent_list.borrow_mut().add_list(i);
-                    }
-                    None => return Err(PacketParseError),
-                },
+                atom => {
+                    let atom = match atom {
+                        Token::String(content) => PacketDatum::String(unescape(content)),
+                        Token::Integer(i) => PacketDatum::Integer(i),
+                        _ => unreachable!("brackets are matched above"),
+                    };
+                    let parent_bracket_idx = open_bracket_indices.last().unwrap();
+                    let parent_list = lists.get(parent_bracket_idx).unwrap();
+                    parent_list
+                        .borrow_mut()
+                        .add_list(Rc::new(RefCell::new(atom)));
+                }
             }
+
+            idx += 1;
         }
 
         if !open_bracket_indices.is_empty() {
             return Err(PacketParseError);
         }
 
-        let root_list = lists.get(&0).unwrap().borrow().clone();
+        // nothing but separators
+        let root_list = lists.get(&0).ok_or(PacketParseError)?.borrow().clone();
 
         Ok(root_list)
     }
 }
 
-fn get_valid_tokens(s: &str) -> Vec<String> {
-    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+#[cfg(test)]
+mod tests {
+    use crate::datum::PacketDatum;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
-    let mut valid_tokens = vec![];
+    #[test]
+    fn all_ints() {
+        let parsed_list: PacketDatum = "[1,2,3]".parse().unwrap();
 
-    let mut chars = s.chars().peekable();
+        let expected_list = PacketDatum::int_list(vec![1, 2, 3]);
 
-    while let Some(c) = chars.next() {
-        match c {
-            '[' | ']' => valid_tokens.push(c.to_string()),
-            '0'..='9' | '-' => {
-                let mut int_str = String::from(c);
+        assert!(parsed_list == expected_list);
+    }
 
-                while let Some(c) = chars.peek() {
-                    if !c.is_numeric() {
-                        break;
-                    }
+    #[test]
+    // used to panic on unwrap
+    fn malformed_integers() {
+        for invalid in ["[-]", "[1,-,2]", "[--1]", "[2147483648]", "[-2147483649]"] {
+            assert!(invalid.parse::<PacketDatum>().is_err());
+        }
 
-                    int_str.push(chars.next().unwrap());
-                }
+        let parsed_list: PacketDatum = "[2147483647,-2147483648]".parse().unwrap();
+        assert!(parsed_list == PacketDatum::int_list(vec![i32::MAX, i32::MIN]));
+    }
 
-                valid_tokens.push(int_str);
-            }
-            _ => (),
-        }
+    // crashes found by the fuzz targets in fuzz/
+
+    #[test]
+    // parse target: "1\x0c1" (form feed) parsed as 11
+    fn whitespace_separates_integers() {
+        let parsed_list: PacketDatum = "[1,1,3,1\x0c1,1]".parse().unwrap();
+        assert!(parsed_list == PacketDatum::int_list(vec![1, 1, 3, 1, 1, 1]));
+
+        let parsed_list: PacketDatum = "[1 2\t3\r\n4]".parse().unwrap();
+        assert!(parsed_list == PacketDatum::int_list(vec![1, 2, 3, 4]));
     }
 
-    valid_tokens
-}
+    #[test]
+    // compare target: [[4,4],4\r2,...] compared as [[4,4],42,...]
+    fn whitespace_separated_integers_compare_like_commas() {
+        let left: PacketDatum = "[[4,4],4,4]".parse().unwrap();
+        let right: PacketDatum = "[[4,4],4\r2\t\t,2,4,4]".parse().unwrap();
 
-#[cfg(test)]
-mod tests {
-    use crate::datum::PacketDatum;
+        assert!(right == "[[4,4],4,2,2,4,4]".parse().unwrap());
+        assert!(left > right);
+    }
 
     #[test]
-    fn all_ints() {
-        let parsed_list: PacketDatum = "[1,2,3]".parse().unwrap();
+    // whitespace around the packet is a separator like any other
+    fn leading_whitespace() {
+        let parsed_list: PacketDatum = " \n[1,2]".parse().unwrap();
+        assert!(parsed_list == PacketDatum::int_list(vec![1, 2]));
+    }
 
-        let expected_list = PacketDatum::int_list(vec![1, 2, 3]);
+    #[test]
+    // parse target: ",[]," was rejected by from_str but accepted by validate_str
+    fn separators_around_the_packet() {
+        let parsed_list: PacketDatum = ",[],".parse().unwrap();
+        assert!(parsed_list == PacketDatum::List(vec![]));
+
+        assert!(",".parse::<PacketDatum>().is_err());
+    }
+
+    #[test]
+    // these used to be skipped over ("[1,a]" was [1]) or cut off ("[1][2]" was [1])
+    fn junk_and_trailing_input() {
+        for invalid in [
+            "[1,a]",
+            " [x] ",
+            "[1][2]",
+            "[1]]",
+            "[1]2",
+            "[1];",
+            "[1,\u{a0}2]",
+            "",
+        ] {
+            assert!(invalid.parse::<PacketDatum>().is_err());
+            assert!(crate::datum::validate_str(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let parsed_list: PacketDatum = r#"[1,"temp",[2,"a\"b,]"],""]"#.parse().unwrap();
+
+        let expected_list = PacketDatum::list(vec![
+            PacketDatum::rc_int(1),
+            PacketDatum::rc_str("temp"),
+            Rc::new(RefCell::new(PacketDatum::list(vec![
+                PacketDatum::rc_int(2),
+                PacketDatum::rc_str("a\"b,]"),
+            ]))),
+            PacketDatum::rc_str(""),
+        ]);
 
         assert!(parsed_list == expected_list);
+        assert!(parsed_list.to_string() == r#"[1,"temp",[2,"a\"b,]"],""]"#);
+
+        for invalid in [
+            r#"["abc]"#,
+            r#"["\x"]"#,
+            "[\"a\nb\"]",
+            r#"["a"#,
+            r#""a"[1]"#,
+        ] {
+            assert!(invalid.parse::<PacketDatum>().is_err());
+        }
     }
 
     #[test]
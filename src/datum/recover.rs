@@ -0,0 +1,479 @@
+use super::escape::{scan_string, unescape, StringError};
+use super::{Diagnostic, DiagnosticKind, PacketDatum};
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+// Checks a packet against the grammar every other parser reads (Tokens in text.rs):
+//
+//     packet = list
+//     list   = "[" { item } "]"
+//     item   = list | integer (an i32) | string (quoted, see escape.rs)
+//
+// with separators, commas and ASCII whitespace, allowed any number of times around any token:
+// "[1 2]", "[1,,2]", "[,1,]" and ",[]," are all fine. It builds what it can of the packet
+// on the way, and finds no errors in exactly the strings validate_str accepts.
+//
+// One pass over the chars with a stack of the open lists: their [ offsets and the children so far.
+// - [ pushes a list, ] pops it into its parent. A ] with nothing open, or a [ still open at the end,
+//   is reported at that bracket, with a note where its partner was expected
+// - once the outermost ] pops, anything but separators is text after the packet
+//
+// Every problem is recorded, then the parser resynchronizes and carries on:
+// - stray ] after the packet: skipped
+// - bad integer, bad string, run of unknown characters: dropped.
+//   A bad string is skipped up to its closing " (an unterminated one to the end)
+// - text before the first [: skipped up to it
+// - text after the packet: the rest of the line is skipped (the next packet is on the next line)
+// - lists still open at the end: closed there, innermost first, with whatever they hold
+
+/// Recovered:
+/// What parse_recovering could make of a packet string.
+///
+/// ex: parse_recovering("[1 x,[3,-,4]") has packet [1,[3,4]] and 3 errors:
+/// unexpected character, invalid integer and the unclosed root list
+#[derive(Debug)]
+pub struct Recovered {
+    /// the packet with the broken parts left out, None if there's no [ at all
+    pub packet: Option<PacketDatum>,
+    /// every problem found, in the order they were found
+    pub errors: Vec<Diagnostic>,
+}
+
+impl Recovered {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Parses s without stopping at the first error.
+///
+/// For well formed packets the packet is the same as s.parse() and errors is empty.
+pub fn parse_recovering(s: &str) -> Recovered {
+    RecoveringParser::new(s).parse()
+}
+
+struct OpenList {
+    // offset of the [
+    start: usize,
+    children: Vec<Rc<RefCell<PacketDatum>>>,
+}
+
+struct RecoveringParser<'a> {
+    source: &'a str,
+    open_lists: Vec<OpenList>,
+    // the outermost list and the offset of its ]
+    root: Option<(PacketDatum, usize)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> RecoveringParser<'a> {
+    fn new(source: &'a str) -> Self {
+        RecoveringParser {
+            source,
+            open_lists: vec![],
+            root: None,
+            errors: vec![],
+        }
+    }
+
+    fn error(&mut self, kind: DiagnosticKind, span: Range<usize>, label: impl Into<String>) {
+        self.errors
+            .push(Diagnostic::new(self.source, kind, span, label));
+    }
+
+    fn error_with_note(
+        &mut self,
+        kind: DiagnosticKind,
+        span: Range<usize>,
+        label: impl Into<String>,
+        note_span: Range<usize>,
+        note: &str,
+    ) {
+        self.errors
+            .push(Diagnostic::new(self.source, kind, span, label).with_note(note_span, note));
+    }
+
+    fn parse(mut self) -> Recovered {
+        let bytes = self.source.as_bytes();
+        let mut idx = 0;
+
+        while idx < bytes.len() {
+            let c = self.source[idx..]
+                .chars()
+                .next()
+                .expect("idx is on a char boundary");
+
+            if c.is_ascii_whitespace() || c == ',' {
+                idx += c.len_utf8();
+                continue;
+            }
+
+            if let Some((_, root_close)) = self.root {
+                let note = (root_close..root_close + 1, "the packet ends here");
+
+                if c == ']' {
+                    self.error_with_note(
+                        DiagnosticKind::UnexpectedClose,
+                        idx..idx + 1,
+                        "no list is open here",
+                        note.0,
+                        note.1,
+                    );
+                    idx += 1;
+                    continue;
+                }
+
+                self.error_with_note(
+                    DiagnosticKind::TextAfterPacket,
+                    idx..idx + c.len_utf8(),
+                    "text after the packet",
+                    note.0,
+                    note.1,
+                );
+                break;
+            }
+
+            if self.open_lists.is_empty() && c != '[' {
+                self.error(
+                    DiagnosticKind::NotAList,
+                    idx..idx + c.len_utf8(),
+                    "expected `[` here",
+                );
+
+                match self.source[idx..].find('[') {
+                    Some(offset) => idx += offset,
+                    None => idx = bytes.len(),
+                }
+                continue;
+            }
+
+            match c {
+                '[' => {
+                    self.open_lists.push(OpenList {
+                        start: idx,
+                        children: vec![],
+                    });
+                    idx += 1;
+                }
+                ']' => {
+                    self.close_list(idx);
+                    idx += 1;
+                }
+                '-' | '0'..='9' => {
+                    let digits_start = if c == '-' { idx + 1 } else { idx };
+                    let end = digits_start
+                        + bytes[digits_start..]
+                            .iter()
+                            .take_while(|b| b.is_ascii_digit())
+                            .count();
+
+                    if end == digits_start {
+                        self.error(
+                            DiagnosticKind::InvalidInteger,
+                            idx..idx + 1,
+                            "expected digits after `-`",
+                        );
+                    } else {
+                        match self.source[idx..end].parse() {
+                            Ok(i) => self.push_child(PacketDatum::Integer(i)),
+                            Err(_) => self.error(
+                                DiagnosticKind::InvalidInteger,
+                                idx..end,
+                                "doesn't fit in an i32",
+                            ),
+                        }
+                    }
+
+                    idx = end;
+                }
+                '"' => {
+                    let end = match scan_string(self.source, idx) {
+                        Ok(end) => {
+                            let string = unescape(&self.source[idx + 1..end - 1]);
+                            self.push_child(PacketDatum::String(string));
+                            end
+                        }
+                        Err(StringError::Unterminated) => {
+                            self.error(
+                                DiagnosticKind::UnterminatedString,
+                                idx..idx + 1,
+                                "this `\"` is never closed",
+                            );
+                            bytes.len()
+                        }
+                        Err(StringError::InvalidEscape(span)) => {
+                            let escape = self.source[span.clone()].to_string();
+                            self.error(
+                                DiagnosticKind::InvalidString,
+                                span,
+                                format!("`{}` isn't a valid escape", escape),
+                            );
+                            skip_string(bytes, idx)
+                        }
+                        Err(StringError::ControlCharacter(offset)) => {
+                            let c = self.source[offset..].chars().next().expect("a char");
+                            self.error(
+                                DiagnosticKind::InvalidString,
+                                offset..offset + c.len_utf8(),
+                                format!("`{}` has to be escaped", c.escape_debug()),
+                            );
+                            skip_string(bytes, idx)
+                        }
+                    };
+
+                    idx = end;
+                }
+                _ => {
+                    let end = idx
+                        + self.source[idx..]
+                            .find(|c: char| {
+                                c.is_ascii_whitespace()
+                                    || matches!(c, '[' | ']' | ',' | '-' | '0'..='9' | '"')
+                            })
+                            .unwrap_or(bytes.len() - idx);
+
+                    let unknown = self.source[idx..end].escape_debug().to_string();
+                    self.error(
+                        DiagnosticKind::InvalidCharacter,
+                        idx..end,
+                        format!("`{}` can't be part of a packet", unknown),
+                    );
+
+                    idx = end;
+                }
+            }
+        }
+
+        if !self.open_lists.is_empty() {
+            let end = self
+                .source
+                .trim_end_matches(|c: char| c.is_ascii_whitespace())
+                .len();
+
+            while let Some(open) = self.open_lists.last().map(|list| list.start) {
+                self.error_with_note(
+                    DiagnosticKind::UnclosedList,
+                    open..open + 1,
+                    "this `[` is never closed",
+                    end..end,
+                    "expected `]` here",
+                );
+                self.close_list(end);
+            }
+        }
+
+        if self.root.is_none() && self.errors.is_empty() {
+            let end = self.source.len();
+            self.error(DiagnosticKind::Empty, end..end, "expected a packet");
+        }
+
+        Recovered {
+            packet: self.root.map(|(packet, _)| packet),
+            errors: self.errors,
+        }
+    }
+
+    fn push_child(&mut self, child: PacketDatum) {
+        self.open_lists
+            .last_mut()
+            .expect("items are only parsed inside a list")
+            .children
+            .push(Rc::new(RefCell::new(child)));
+    }
+
+    // closes the innermost open list at the ] at idx
+    fn close_list(&mut self, idx: usize) {
+        let list = self.open_lists.pop().expect("a list is open");
+        let packet_datum = PacketDatum::List(list.children);
+
+        match self.open_lists.is_empty() {
+            true => self.root = Some((packet_datum, idx)),
+            false => self.push_child(packet_datum),
+        }
+    }
+}
+
+// the offset after the " closing the string that starts at start, whatever is wrong inside it,
+// or the end of the text if it's never closed
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let mut pos = start + 1;
+
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'"' => return pos + 1,
+            b'\\' => pos += 2,
+            _ => pos += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::fuzz_corpus;
+    use crate::datum::validate_str;
+
+    fn kinds(recovered: &Recovered) -> Vec<DiagnosticKind> {
+        recovered.errors.iter().map(|e| e.kind()).collect()
+    }
+
+    #[test]
+    fn well_formed_packets() {
+        for s in [
+            "[]",
+            "[1,[2,[3]],-4]",
+            " [ 1 , [ ] ]\n",
+            "[[[]]]",
+            r#"["a,]",["\""]]"#,
+        ] {
+            let recovered = parse_recovering(s);
+
+            assert!(recovered.is_ok());
+            assert!(recovered.packet == Some(s.parse().unwrap()));
+        }
+    }
+
+    #[test]
+    // separators are commas and whitespace, any number of them, like every other parser
+    fn separators() {
+        for s in [
+            "[1 2]",
+            "[1,]",
+            "[,1]",
+            "[1,,2]",
+            ",[],",
+            "[[1][2]]",
+            "[1\t,\n2]",
+        ] {
+            let recovered = parse_recovering(s);
+
+            assert!(recovered.is_ok());
+            assert!(recovered.packet == Some(s.parse().unwrap()));
+        }
+    }
+
+    #[test]
+    // no errors exactly when validate_str accepts, and then the same packet as from_str
+    fn agrees_with_validate_str_on_fuzz_corpus() {
+        for s in fuzz_corpus() {
+            let recovered = parse_recovering(&s);
+
+            assert!(recovered.is_ok() == validate_str(&s).is_ok());
+            if recovered.is_ok() {
+                assert!(recovered.packet == Some(s.parse().unwrap()));
+            }
+        }
+    }
+
+    #[test]
+    fn collects_every_error() {
+        let recovered = parse_recovering("[1 x,[3,-,4]");
+
+        assert!(
+            kinds(&recovered)
+                == vec![
+                    DiagnosticKind::InvalidCharacter,
+                    DiagnosticKind::InvalidInteger,
+                    DiagnosticKind::UnclosedList
+                ]
+        );
+        assert!(recovered.errors[2].span() == (0..1));
+        assert!(recovered.packet.unwrap().to_string() == "[1,[3,4]]");
+    }
+
+    #[test]
+    // bad integers and unknown characters are left out, the rest of the list is kept
+    fn partial_tree() {
+        let recovered = parse_recovering("[1,-,abc,99999999999,[2,x],3,]");
+
+        assert!(
+            kinds(&recovered)
+                == vec![
+                    DiagnosticKind::InvalidInteger,
+                    DiagnosticKind::InvalidCharacter,
+                    DiagnosticKind::InvalidInteger,
+                    DiagnosticKind::InvalidCharacter,
+                ]
+        );
+        assert!(recovered.errors[1].label() == "`abc` can't be part of a packet");
+        assert!(recovered.packet.unwrap().to_string() == "[1,[2],3]");
+    }
+
+    #[test]
+    // a bad string is dropped whole, brackets and commas inside it don't count
+    fn bad_strings() {
+        let recovered = parse_recovering("[\"ok\",\"b\\q],\",[\"tab\t\"],\"x\"\"y\"]");
+
+        assert!(
+            kinds(&recovered)
+                == vec![DiagnosticKind::InvalidString, DiagnosticKind::InvalidString,]
+        );
+        assert!(recovered.errors[0].span() == (8..10));
+        assert!(recovered.errors[0].label() == r#"`\q` isn't a valid escape"#);
+        assert!(recovered.errors[1].label() == r#"`\t` has to be escaped"#);
+        assert!(recovered.packet.unwrap().to_string() == r#"["ok",[],"x","y"]"#);
+
+        let recovered = parse_recovering(r#"[1,"abc]"#);
+        assert!(
+            kinds(&recovered)
+                == vec![
+                    DiagnosticKind::UnterminatedString,
+                    DiagnosticKind::UnclosedList
+                ]
+        );
+        assert!(recovered.packet.unwrap().to_string() == "[1]");
+
+        // an escaped multi byte char is reported whole (found by fuzzing)
+        let recovered = parse_recovering(r#"["\Ϥ",2]"#);
+        assert!(recovered.errors[0].span() == (2..5));
+        assert!(recovered.errors[0].label() == r#"`\Ϥ` isn't a valid escape"#);
+    }
+
+    #[test]
+    fn unclosed_lists_innermost_first() {
+        let recovered = parse_recovering("[1,[2,[3");
+
+        assert!(recovered.errors.len() == 3);
+        assert!(recovered
+            .errors
+            .iter()
+            .map(|e| e.span().start)
+            .eq([6, 3, 0]));
+        assert!(recovered.packet.unwrap().to_string() == "[1,[2,[3]]]");
+    }
+
+    #[test]
+    fn text_around_the_packet() {
+        let recovered = parse_recovering("xy [1]] [2]");
+
+        assert!(
+            kinds(&recovered)
+                == vec![
+                    DiagnosticKind::NotAList,
+                    DiagnosticKind::UnexpectedClose,
+                    DiagnosticKind::TextAfterPacket
+                ]
+        );
+        assert!(recovered.packet.unwrap().to_string() == "[1]");
+
+        let recovered = parse_recovering("1,2");
+        assert!(kinds(&recovered) == vec![DiagnosticKind::NotAList]);
+        assert!(recovered.packet.is_none());
+
+        let recovered = parse_recovering(" ");
+        assert!(kinds(&recovered) == vec![DiagnosticKind::Empty]);
+        assert!(recovered.packet.is_none());
+    }
+
+    #[test]
+    // same whitespace as the packet grammar: a vertical tab isn't a separator
+    fn only_ascii_whitespace_separates() {
+        let recovered = parse_recovering("[\u{b}\n\n]");
+
+        assert!(kinds(&recovered) == vec![DiagnosticKind::InvalidCharacter]);
+        assert!(validate_str("[\u{b}\n\n]").is_err());
+    }
+}
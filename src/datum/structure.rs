@@ -0,0 +1,394 @@
+use super::PacketDatum;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+// Children are Rc<RefCell<PacketDatum>>, so nothing stops a list from being pushed into itself:
+//
+//     let list = Rc::new(RefCell::new(PacketDatum::List(vec![])));
+//     list.borrow_mut().add_list(Rc::clone(&list));
+//
+// cmp, == and Hash walk children recursively, so on a cycle they never finish
+// (they overflow the stack), and the Rcs of a cycle are never dropped.
+// Sharing the same child between two lists (or twice in one list) doesn't loop,
+// but mutating it through one parent silently changes the other.
+//
+// - validate() walks the tree once, keeping the Rcs on the current path and every Rc seen:
+//     an Rc already on the path is a cycle, an Rc seen somewhere else is a shared subtree
+// - try_push() refuses a child whose subtree contains the list it's being pushed into,
+//     or that already has a cycle. It keeps the same two sets as validate(), so it finishes
+//     on a cyclic child and searches a shared subtree once instead of once per path.
+//     A node in that subtree that's mutably borrowed somewhere else can't be looked into,
+//     so there's no telling whether the list is under it: that's its own error, not a guess
+// - deep_clone() copies every node, so the copy shares nothing with the original
+
+/// Structure Module:
+/// Detecting and avoiding cycles and shared subtrees.
+///
+/// Contains:
+/// - PacketDatum::validate: Err if the tree has a cycle or a child reachable twice
+/// - PacketDatum::try_push: push a child, unless it would create a cycle
+/// - PacketDatum::deep_clone: copy that shares no nodes with the original
+/// - StructureError
+#[derive(Debug, PartialEq, Eq)]
+pub enum StructureError {
+    /// The list at this path (child indices from the root) contains itself.
+    Cycle { path: Vec<usize> },
+    /// The node at this path was already reached through another path.
+    Shared { path: Vec<usize> },
+    /// Tried to push a child into an Integer or a String.
+    NotAList,
+    /// The node at this path (child indices from the child being pushed) is mutably borrowed,
+    /// so try_push couldn't check it for cycles.
+    Borrowed { path: Vec<usize> },
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::Cycle { path } => write!(f, "list at {:?} contains itself", path),
+            StructureError::Shared { path } => {
+                write!(
+                    f,
+                    "node at {:?} is shared with another part of the packet",
+                    path
+                )
+            }
+            StructureError::NotAList => write!(f, "only lists can have children"),
+            StructureError::Borrowed { path } => {
+                write!(
+                    f,
+                    "node at {:?} is mutably borrowed, so it can't be checked for cycles",
+                    path
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl PacketDatum {
+    /// Ok if the packet is a plain tree: no cycles and no node reachable through two paths.
+    ///
+    /// Packets built by the parser always are. Run this before comparing, hashing
+    /// or printing a packet that was assembled by hand.
+    pub fn validate(&self) -> Result<(), StructureError> {
+        let mut on_path = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut path = vec![];
+
+        self.validate_children(&mut on_path, &mut seen, &mut path)
+    }
+
+    fn validate_children(
+        &self,
+        on_path: &mut HashSet<usize>,
+        seen: &mut HashSet<usize>,
+        path: &mut Vec<usize>,
+    ) -> Result<(), StructureError> {
+        let l = match self {
+            PacketDatum::List(l) => l,
+            PacketDatum::Integer(_) | PacketDatum::String(_) => return Ok(()),
+        };
+
+        for (idx, child) in l.iter().enumerate() {
+            path.push(idx);
+            let address = Rc::as_ptr(child) as usize;
+
+            if on_path.contains(&address) {
+                return Err(StructureError::Cycle { path: path.clone() });
+            }
+
+            if !seen.insert(address) {
+                return Err(StructureError::Shared { path: path.clone() });
+            }
+
+            on_path.insert(address);
+            child.borrow().validate_children(on_path, seen, path)?;
+            on_path.remove(&address);
+
+            path.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Pushes a child onto this list, unless the child's subtree contains this list
+    /// or already has a cycle of its own (both are StructureError::Cycle, the path is
+    /// child indices from the child). Shared subtrees are searched once.
+    ///
+    /// Safe to call through parent.borrow_mut(): this list is found by address without
+    /// borrowing it. Any other node in the child's subtree that's mutably borrowed right now
+    /// can't be looked into, so the push is refused with StructureError::Borrowed.
+    pub fn try_push(&mut self, child: Rc<RefCell<PacketDatum>>) -> Result<(), StructureError> {
+        if !matches!(self, PacketDatum::List(_)) {
+            return Err(StructureError::NotAList);
+        }
+
+        let this = self as *const PacketDatum;
+        let mut on_path = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut path = vec![];
+
+        if contains_node(&child, this, &mut on_path, &mut seen, &mut path)? {
+            return Err(StructureError::Cycle { path });
+        }
+
+        self.add_list(child);
+
+        Ok(())
+    }
+
+    /// Copies every node, so the copy shares nothing with the original (unlike clone,
+    /// which shares the children's Rcs). Err if the packet has a cycle.
+    pub fn deep_clone(&self) -> Result<PacketDatum, StructureError> {
+        let mut on_path = HashSet::new();
+        let mut path = vec![];
+
+        self.deep_clone_inner(&mut on_path, &mut path)
+    }
+
+    fn deep_clone_inner(
+        &self,
+        on_path: &mut HashSet<usize>,
+        path: &mut Vec<usize>,
+    ) -> Result<PacketDatum, StructureError> {
+        let l = match self {
+            PacketDatum::List(l) => l,
+            atom => return Ok(atom.clone()),
+        };
+
+        let mut children = Vec::with_capacity(l.len());
+
+        for (idx, child) in l.iter().enumerate() {
+            path.push(idx);
+            let address = Rc::as_ptr(child) as usize;
+
+            if !on_path.insert(address) {
+                return Err(StructureError::Cycle { path: path.clone() });
+            }
+
+            let copy = child.borrow().deep_clone_inner(on_path, path)?;
+            children.push(Rc::new(RefCell::new(copy)));
+
+            on_path.remove(&address);
+            path.pop();
+        }
+
+        Ok(PacketDatum::List(children))
+    }
+}
+
+// does node's subtree contain target? path ends up pointing at where it was found,
+// or at the node that couldn't be borrowed to look inside.
+// Like validate: a node already on the path is a cycle the child had before the push,
+// and a node seen through another path has already been searched, so it isn't searched again.
+fn contains_node(
+    node: &Rc<RefCell<PacketDatum>>,
+    target: *const PacketDatum,
+    on_path: &mut HashSet<usize>,
+    seen: &mut HashSet<usize>,
+    path: &mut Vec<usize>,
+) -> Result<bool, StructureError> {
+    if std::ptr::eq(node.as_ptr(), target) {
+        return Ok(true);
+    }
+
+    let address = Rc::as_ptr(node) as usize;
+
+    if on_path.contains(&address) {
+        return Err(StructureError::Cycle { path: path.clone() });
+    }
+
+    if !seen.insert(address) {
+        return Ok(false);
+    }
+
+    let packet_datum = node
+        .try_borrow()
+        .map_err(|_| StructureError::Borrowed { path: path.clone() })?;
+
+    on_path.insert(address);
+
+    if let PacketDatum::List(l) = &*packet_datum {
+        for (idx, child) in l.iter().enumerate() {
+            path.push(idx);
+
+            if contains_node(child, target, on_path, seen, path)? {
+                return Ok(true);
+            }
+
+            path.pop();
+        }
+    }
+
+    on_path.remove(&address);
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rc_list() -> Rc<RefCell<PacketDatum>> {
+        Rc::new(RefCell::new(PacketDatum::List(vec![])))
+    }
+
+    // breaks the cycle so the test doesn't leak
+    fn clear(list: &Rc<RefCell<PacketDatum>>) {
+        *list.borrow_mut() = PacketDatum::List(vec![]);
+    }
+
+    #[test]
+    fn parsed_packets_are_valid() {
+        let packet: PacketDatum = "[1,[2,[3,[4,[5,6,7]]]],8,9]".parse().unwrap();
+
+        assert!(packet.validate().is_ok());
+    }
+
+    #[test]
+    // root: [list], list: [1, list]
+    fn self_referential_list() {
+        let list = rc_list();
+        list.borrow_mut().add_list(PacketDatum::rc_int(1));
+        list.borrow_mut().add_list(Rc::clone(&list));
+
+        let root = PacketDatum::list(vec![Rc::clone(&list)]);
+
+        assert!(root.validate() == Err(StructureError::Cycle { path: vec![0, 1] }));
+        assert!(root.deep_clone() == Err(StructureError::Cycle { path: vec![0, 1] }));
+
+        clear(&list);
+    }
+
+    #[test]
+    // a: [b], b: [a]
+    fn longer_cycle() {
+        let (a, b) = (rc_list(), rc_list());
+        a.borrow_mut().add_list(Rc::clone(&b));
+        b.borrow_mut().add_list(Rc::clone(&a));
+
+        let root = PacketDatum::list(vec![Rc::clone(&a)]);
+        assert!(
+            root.validate()
+                == Err(StructureError::Cycle {
+                    path: vec![0, 0, 0]
+                })
+        );
+
+        clear(&a);
+    }
+
+    #[test]
+    // [shared, shared]
+    fn shared_subtree() {
+        let shared = Rc::new(RefCell::new(PacketDatum::int_list(vec![2])));
+        let root = PacketDatum::list(vec![Rc::clone(&shared), Rc::clone(&shared)]);
+
+        assert!(root.validate() == Err(StructureError::Shared { path: vec![1] }));
+
+        // mutating through one parent changes the other
+        shared.borrow_mut().add_list(PacketDatum::rc_int(3));
+        assert!(root == "[[2,3],[2,3]]".parse().unwrap());
+    }
+
+    #[test]
+    fn deep_clone_breaks_sharing() {
+        let shared = Rc::new(RefCell::new(PacketDatum::int_list(vec![2])));
+        let root = PacketDatum::list(vec![Rc::clone(&shared), Rc::clone(&shared)]);
+
+        let copy = root.deep_clone().unwrap();
+        assert!(copy == root);
+        assert!(copy.validate().is_ok());
+
+        shared.borrow_mut().add_list(PacketDatum::rc_int(3));
+        assert!(copy == "[[2],[2]]".parse().unwrap());
+    }
+
+    #[test]
+    fn try_push_refuses_cycles() {
+        let list = rc_list();
+
+        // into itself
+        let result = list.borrow_mut().try_push(Rc::clone(&list));
+        assert!(result == Err(StructureError::Cycle { path: vec![] }));
+
+        // into its own child
+        let child = rc_list();
+        list.borrow_mut().try_push(Rc::clone(&child)).unwrap();
+        let result = child.borrow_mut().try_push(Rc::clone(&list));
+        assert!(result == Err(StructureError::Cycle { path: vec![0] }));
+
+        // a plain value being built up
+        let mut root = PacketDatum::List(vec![]);
+        root.try_push(Rc::clone(&list)).unwrap();
+        root.try_push(PacketDatum::rc_int(4)).unwrap();
+        assert!(root == "[[[]],4]".parse().unwrap());
+        assert!(root.validate().is_ok());
+
+        let mut integer = PacketDatum::Integer(1);
+        assert!(integer.try_push(rc_list()) == Err(StructureError::NotAList));
+
+        let mut string = PacketDatum::String("a".to_string());
+        assert!(string.try_push(rc_list()) == Err(StructureError::NotAList));
+    }
+
+    #[test]
+    // a node borrowed by someone else isn't a cycle, it just can't be checked
+    fn try_push_reports_borrowed_nodes() {
+        let list = rc_list();
+        let unrelated = rc_list();
+        let child = rc_list();
+        child.borrow_mut().add_list(PacketDatum::rc_int(1));
+        child.borrow_mut().add_list(Rc::clone(&unrelated));
+
+        {
+            let _elsewhere = unrelated.borrow_mut();
+            let result = list.borrow_mut().try_push(Rc::clone(&child));
+            assert!(result == Err(StructureError::Borrowed { path: vec![1] }));
+            assert!(*list.borrow() == PacketDatum::List(vec![]));
+        }
+
+        // once it's released the push goes through
+        list.borrow_mut().try_push(child).unwrap();
+        assert!(*list.borrow() == "[[1,[]]]".parse().unwrap());
+    }
+
+    #[test]
+    // a: [b], b: [a], pushed into an unrelated list: the walk stops instead of looping forever
+    fn try_push_refuses_cyclic_children() {
+        let (a, b) = (rc_list(), rc_list());
+        a.borrow_mut().add_list(Rc::clone(&b));
+        b.borrow_mut().add_list(Rc::clone(&a));
+
+        let list = rc_list();
+        let result = list.borrow_mut().try_push(Rc::clone(&a));
+        assert!(result == Err(StructureError::Cycle { path: vec![0, 0] }));
+        assert!(*list.borrow() == PacketDatum::List(vec![]));
+
+        clear(&a);
+    }
+
+    #[test]
+    // 40 levels of [shared, shared]: 2^40 paths, but only 40 distinct nodes to search
+    fn try_push_searches_shared_subtrees_once() {
+        let leaf = rc_list();
+        let mut child = Rc::clone(&leaf);
+        for _ in 0..40 {
+            child = Rc::new(RefCell::new(PacketDatum::list(vec![
+                Rc::clone(&child),
+                Rc::clone(&child),
+            ])));
+        }
+
+        let list = rc_list();
+        list.borrow_mut().try_push(Rc::clone(&child)).unwrap();
+
+        // and the list is still found above the leaf
+        let result = leaf.borrow_mut().try_push(Rc::clone(&list));
+        assert!(result == Err(StructureError::Cycle { path: vec![0; 41] }));
+    }
+}
@@ -0,0 +1,279 @@
+use super::escape::{unescape, write_quoted};
+use super::{PacketDatum, PacketParseError, Token, Tokens};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// PacketDatum's children are Rc<RefCell<..>>: neither Rc nor RefCell can be shared between
+// threads, so a PacketDatum can't be sent to another thread or task at all.
+//
+// SyncPacketDatum is the same shape built for threads:
+// a list is an Arc<[SyncPacketDatum]>, immutable once built. Cloning is an Arc clone,
+// and since nothing can be mutated there's no RefCell and nothing to lock.
+// That makes it Send + Sync without any unsafe code.
+
+/// SyncPacketDatum Enum:
+/// Immutable, thread safe version of PacketDatum.
+///
+/// Same variants, same Ord (Day 13 rules), and parses the same packet strings.
+/// Converts to and from PacketDatum with From.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SyncPacketDatum {
+    List(Arc<[SyncPacketDatum]>),
+    Integer(i32),
+    String(Arc<str>),
+}
+
+impl SyncPacketDatum {
+    pub fn int_list(list: &[i32]) -> SyncPacketDatum {
+        SyncPacketDatum::List(list.iter().map(|i| SyncPacketDatum::Integer(*i)).collect())
+    }
+}
+
+// same as PacketDatum: an atom compared with a list becomes a list holding just that atom,
+// and integers sort before strings
+impl Ord for SyncPacketDatum {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Integer(i1), Self::Integer(i2)) => i1.cmp(i2),
+            (Self::String(s1), Self::String(s2)) => s1.cmp(s2),
+            (Self::Integer(_), Self::String(_)) => Ordering::Less,
+            (Self::String(_), Self::Integer(_)) => Ordering::Greater,
+            (Self::List(l1), Self::List(l2)) => l1.iter().cmp(l2.iter()),
+            (Self::List(l1), _) => l1.iter().cmp(std::slice::from_ref(other)),
+            (_, Self::List(l2)) => std::slice::from_ref(self).iter().cmp(l2.iter()),
+        }
+    }
+}
+
+impl PartialOrd for SyncPacketDatum {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// same output as PacketDatum's Display
+impl fmt::Display for SyncPacketDatum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncPacketDatum::Integer(i) => write!(f, "{}", i),
+            SyncPacketDatum::String(s) => write_quoted(f, s),
+            SyncPacketDatum::List(l) => {
+                write!(f, "[")?;
+
+                for (idx, packet_datum) in l.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{}", packet_datum)?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+// Accepts exactly what PacketDatum::from_str accepts (the grammar of validate_str),
+// and builds the same packet.
+//
+// The text is parsed straight into SyncPacketDatums: the children of every open list
+// are collected in a Vec on a stack, and become an Arc<[..]> when the list closes.
+impl FromStr for SyncPacketDatum {
+    type Err = PacketParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokens::new(s);
+        let mut open_lists: Vec<Vec<SyncPacketDatum>> = vec![];
+        let mut root = None;
+
+        while let Some(token) = tokens.next_token()? {
+            match token {
+                Token::Open => open_lists.push(vec![]),
+                Token::Close => {
+                    let list =
+                        SyncPacketDatum::List(open_lists.pop().ok_or(PacketParseError)?.into());
+
+                    match open_lists.last_mut() {
+                        Some(parent) => parent.push(list),
+                        None => root = Some(list),
+                    }
+                }
+                Token::Integer(i) => open_lists
+                    .last_mut()
+                    .ok_or(PacketParseError)?
+                    .push(SyncPacketDatum::Integer(i)),
+                Token::String(content) => open_lists
+                    .last_mut()
+                    .ok_or(PacketParseError)?
+                    .push(SyncPacketDatum::String(unescape(content).into())),
+            }
+        }
+
+        root.ok_or(PacketParseError)
+    }
+}
+
+impl From<&PacketDatum> for SyncPacketDatum {
+    fn from(packet_datum: &PacketDatum) -> Self {
+        match packet_datum {
+            PacketDatum::Integer(i) => SyncPacketDatum::Integer(*i),
+            PacketDatum::String(s) => SyncPacketDatum::String(s.as_str().into()),
+            PacketDatum::List(l) => SyncPacketDatum::List(
+                l.iter()
+                    .map(|child| SyncPacketDatum::from(&*child.borrow()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<PacketDatum> for SyncPacketDatum {
+    fn from(packet_datum: PacketDatum) -> Self {
+        SyncPacketDatum::from(&packet_datum)
+    }
+}
+
+impl From<&SyncPacketDatum> for PacketDatum {
+    fn from(packet_datum: &SyncPacketDatum) -> Self {
+        match packet_datum {
+            SyncPacketDatum::Integer(i) => PacketDatum::Integer(*i),
+            SyncPacketDatum::String(s) => PacketDatum::String(s.to_string()),
+            SyncPacketDatum::List(l) => PacketDatum::List(
+                l.iter()
+                    .map(|child| Rc::new(RefCell::new(PacketDatum::from(child))))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<SyncPacketDatum> for PacketDatum {
+    fn from(packet_datum: SyncPacketDatum) -> Self {
+        PacketDatum::from(&packet_datum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyncPacketDatum;
+    use crate::datum::tests::{fuzz_corpus, EXAMPLE_PACKETS};
+    use crate::datum::PacketDatum;
+    use std::thread;
+
+    // compile time: fails to build if SyncPacketDatum stops being Send + Sync
+    const _: fn() = || {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SyncPacketDatum>();
+    };
+
+    const STRING_PACKETS: [&str; 4] = [
+        r#"[1,"temp",[2,3]]"#,
+        r#"[["temp"],"a\"b"]"#,
+        r#"["",[[]]]"#,
+        r#"["😀\n"]"#,
+    ];
+
+    #[test]
+    fn parse() {
+        let packet: SyncPacketDatum = "[[1],4]".parse().unwrap();
+        let expected = SyncPacketDatum::List(
+            vec![SyncPacketDatum::int_list(&[1]), SyncPacketDatum::Integer(4)].into(),
+        );
+
+        assert!(packet == expected);
+
+        for invalid in ["", "1", "[1,2", "[1,2]]", "]"] {
+            assert!(invalid.parse::<SyncPacketDatum>().is_err());
+        }
+    }
+
+    #[test]
+    // separators like PacketDatum's parser, but junk and trailing text are errors, not dropped
+    fn parses_like_packet_datum() {
+        for s in ["[1 2]", "[[1],,4]", " [1]\n", ",[],"] {
+            let packet_datum: PacketDatum = s.parse().unwrap();
+            let sync_packet_datum: SyncPacketDatum = s.parse().unwrap();
+
+            assert!(sync_packet_datum == SyncPacketDatum::from(&packet_datum));
+        }
+
+        for s in ["[1,a]", "[1][2]", " [x] ", "[1]]", "[1]2"] {
+            assert!(s.parse::<SyncPacketDatum>().is_err());
+            assert!(s.parse::<PacketDatum>().is_err());
+        }
+    }
+
+    #[test]
+    // every line of every fuzz seed, and every seed as a whole:
+    // both parsers accept it or both reject it, and build the same packet
+    fn matches_packet_datum_on_fuzz_corpus() {
+        for s in fuzz_corpus() {
+            let packet_datum = s.parse::<PacketDatum>();
+            let sync_packet_datum = s.parse::<SyncPacketDatum>();
+
+            assert!(packet_datum.is_ok() == sync_packet_datum.is_ok());
+            if let (Ok(p), Ok(sync_p)) = (packet_datum, sync_packet_datum) {
+                assert!(SyncPacketDatum::from(&p) == sync_p);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_packet_datum() {
+        for s1 in EXAMPLE_PACKETS.iter().chain(&STRING_PACKETS) {
+            let sync_1: SyncPacketDatum = s1.parse().unwrap();
+            let packet_datum_1: PacketDatum = s1.parse().unwrap();
+
+            // conversions both ways
+            assert!(sync_1.to_string() == packet_datum_1.to_string());
+            assert!(SyncPacketDatum::from(&packet_datum_1) == sync_1);
+            assert!(PacketDatum::from(&sync_1) == packet_datum_1);
+
+            for s2 in EXAMPLE_PACKETS.iter().chain(&STRING_PACKETS) {
+                let sync_2: SyncPacketDatum = s2.parse().unwrap();
+                let packet_datum_2: PacketDatum = s2.parse().unwrap();
+
+                assert!(sync_1.cmp(&sync_2) == packet_datum_1.cmp(&packet_datum_2));
+            }
+        }
+    }
+
+    #[test]
+    // Part 2 sorted on another thread
+    fn send_between_threads() {
+        let packets: Vec<SyncPacketDatum> =
+            EXAMPLE_PACKETS.iter().map(|s| s.parse().unwrap()).collect();
+
+        let sorted = thread::spawn(move || {
+            let mut packets = packets;
+            packets.sort();
+            packets
+        })
+        .join()
+        .unwrap();
+
+        let divider_1: SyncPacketDatum = "[[2]]".parse().unwrap();
+        let divider_2: SyncPacketDatum = "[[6]]".parse().unwrap();
+        let position = |divider| sorted.iter().position(|p| *p == divider).unwrap() + 1;
+
+        assert!(position(divider_1) * position(divider_2) == 140);
+    }
+
+    #[test]
+    // the same packet read by several threads at once
+    fn shared_between_threads() {
+        let packet: SyncPacketDatum = "[1,[2,[3,[4,[5,6,7]]]],8,9]".parse().unwrap();
+        let other: SyncPacketDatum = "[1,[2,[3,[4,[5,6,0]]]],8,9]".parse().unwrap();
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| assert!(packet > other));
+            }
+        });
+    }
+}
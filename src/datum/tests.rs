@@ -1,5 +1,54 @@
 use super::*;
 
+/// The Day 13 example: its 8 pairs, then the divider packets [[2]] and [[6]].
+pub(crate) const EXAMPLE_PACKETS: [&str; 18] = [
+    "[1,1,3,1,1]",
+    "[1,1,5,1,1]",
+    "[[1],[2,3,4]]",
+    "[[1],4]",
+    "[9]",
+    "[[8,7,6]]",
+    "[[4,4],4,4]",
+    "[[4,4],4,4,4]",
+    "[7,7,7,7]",
+    "[7,7,7]",
+    "[]",
+    "[3]",
+    "[[[]]]",
+    "[[]]",
+    "[1,[2,[3,[4,[5,6,7]]]],8,9]",
+    "[1,[2,[3,[4,[5,6,0]]]],8,9]",
+    "[[2]]",
+    "[[6]]",
+];
+
+/// s parsed with from_str, for tests that only write valid packets.
+pub(crate) fn parse(s: &str) -> PacketDatum {
+    s.parse().unwrap()
+}
+
+/// Every line of every seed in fuzz/corpus, and every seed as a whole:
+/// a mix of well formed and malformed packet text for differential tests between parsers.
+pub(crate) fn fuzz_corpus() -> Vec<String> {
+    let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+    let mut texts = vec![];
+
+    for target in std::fs::read_dir(corpus).unwrap() {
+        for seed in std::fs::read_dir(target.unwrap().path()).unwrap() {
+            let bytes = std::fs::read(seed.unwrap().path()).unwrap();
+            let Ok(text) = std::str::from_utf8(&bytes) else {
+                continue;
+            };
+
+            texts.extend(text.lines().chain([text]).map(str::to_string));
+        }
+    }
+
+    assert!(!texts.is_empty());
+
+    texts
+}
+
 #[test]
 fn empty_packet_comparison() {
     let packet_1: Vec<PacketDatum> = vec![];
@@ -276,3 +325,12 @@ mod advent_of_code_examples_comparisons {
         assert!(packet_1 > packet_2);
     }
 }
+
+#[test]
+fn display() {
+    let packet: PacketDatum = "[1, [2,[3, -4]], [], 5]".parse().unwrap();
+
+    assert!(packet.to_string() == "[1,[2,[3,-4]],[],5]");
+    assert!(format!("{:?}", packet) == "[1,[2,[3,-4]],[],5]");
+    assert!(PacketDatum::Integer(7).to_string() == "7");
+}
@@ -0,0 +1,414 @@
+use super::escape::{decoded_chars, scan_string};
+use super::PacketParseError;
+use std::cmp::Ordering;
+
+// The packet grammar:
+//
+//     packet = list
+//     list   = "[" { item } "]"
+//     item   = list | integer (an i32) | string (quoted, see escape.rs)
+//
+// with separators, commas and ASCII whitespace, allowed any number of times around any token.
+// Anything else outside a string is an error, and so is anything but separators after the packet.
+//
+// Tokens below is the reader of that grammar: from_str, SyncPacketDatum's from_str, validate_str,
+// compare_str and the arena parser all take their tokens from it. The parsers that can't use it
+// read the same grammar by hand and are tested against validate_str: parse_recovering (and so
+// diagnose), which has to keep going past errors, and StreamParser, which gets its bytes in pieces.
+//
+// Three parsers read other grammars on purpose:
+// - PacketDatum::from_json_str: only valid JSON, so exactly one comma between items
+//   and no leading zeros
+// - Dialect::parse: its own delimiters and separator, exactly one separator between items
+// - PacketGenerator::template: this grammar plus _ and * as items
+//
+// Comparing packets straight from their text.
+//
+// Parsing builds an Rc<RefCell<PacketDatum>> for every list and integer just so cmp can walk them.
+// When all we need is a < b, we can walk both strings at the same time instead:
+// two cursors hand out tokens ([, ], integers, strings) one at a time and the comparison
+// consumes them in the same order cmp would visit the tree.
+//
+// A string token borrows the text between its quotes, escapes and all.
+// Two of them are compared by decoding both char by char as they're read.
+//
+// Atom promotion without building [n]:
+// comparing the atom (integer or string) n with a list only ever reads from the list's cursor,
+// n itself is just a token we hold onto.
+//  - the list is empty:             [n] is longer, Greater
+//  - compare n with the first item: return if not Equal
+//  - the list has more items:       [n] ran out first, Less
+//
+// Once the ordering is known the rest of both strings is still read (without comparing),
+// so malformed text is an Err no matter where the mistake is.
+
+/// Compares two packet strings with the same rules as Ord for PacketDatum,
+/// without parsing them into PacketDatums and without allocating.
+///
+/// Commas and whitespace separate tokens. Anything else that isn't a bracket, an integer
+/// or a quoted string, unbalanced brackets, or text after the outermost list closes
+/// is a PacketParseError.
+pub fn compare_str(left: &str, right: &str) -> Result<Ordering, PacketParseError> {
+    let mut left_tokens = Tokens::new(left);
+    let mut right_tokens = Tokens::new(right);
+
+    let left_root = left_tokens.next_token()?;
+    let right_root = right_tokens.next_token()?;
+
+    if left_root != Some(Token::Open) || right_root != Some(Token::Open) {
+        return Err(PacketParseError);
+    }
+
+    let ordering = compare_lists(&mut left_tokens, &mut right_tokens)?;
+
+    left_tokens.finish()?;
+    right_tokens.finish()?;
+
+    Ok(ordering)
+}
+
+/// compare_str for text validate_str has already accepted: it stops reading both strings
+/// as soon as the ordering is known instead of checking the rest of them.
+pub(crate) fn compare_validated(left: &str, right: &str) -> Ordering {
+    let mut left_tokens = Tokens::new(left);
+    let mut right_tokens = Tokens::new(right);
+
+    // both roots are [
+    let _ = (left_tokens.next_token(), right_tokens.next_token());
+
+    compare_lists(&mut left_tokens, &mut right_tokens).expect("validated packets")
+}
+
+/// Checks a packet string is well formed (the same rules compare_str uses),
+/// without building a PacketDatum.
+pub fn validate_str(s: &str) -> Result<(), PacketParseError> {
+    let mut tokens = Tokens::new(s);
+
+    if tokens.next_token()? != Some(Token::Open) {
+        return Err(PacketParseError);
+    }
+
+    tokens.finish()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Token<'a> {
+    Open,
+    Close,
+    Integer(i32),
+    /// what's between the quotes, still escaped
+    String(&'a str),
+}
+
+/// Cursor over the tokens of a packet string.
+///
+/// Keeps track of bracket depth, so it can reject a ] with nothing to close
+/// and anything after the outermost list closes.
+#[derive(Clone)]
+pub(crate) struct Tokens<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    root_closed: bool,
+}
+
+impl<'a> Tokens<'a> {
+    pub(crate) fn new(s: &'a str) -> Self {
+        Tokens {
+            source: s,
+            bytes: s.as_bytes(),
+            pos: 0,
+            depth: 0,
+            root_closed: false,
+        }
+    }
+
+    /// Ok(None) once the string is used up
+    pub(crate) fn next_token(&mut self) -> Result<Option<Token<'a>>, PacketParseError> {
+        while self.pos < self.bytes.len()
+            && (self.bytes[self.pos] == b',' || self.bytes[self.pos].is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+
+        let c = match self.bytes.get(self.pos) {
+            Some(c) => *c,
+            None => return Ok(None),
+        };
+
+        if self.root_closed {
+            return Err(PacketParseError);
+        }
+
+        let token = match c {
+            b'[' => {
+                self.pos += 1;
+                self.depth += 1;
+                Token::Open
+            }
+            b']' => {
+                if self.depth == 0 {
+                    return Err(PacketParseError);
+                }
+
+                self.pos += 1;
+                self.depth -= 1;
+                self.root_closed = self.depth == 0;
+                Token::Close
+            }
+            b'-' | b'0'..=b'9' => Token::Integer(self.integer()?),
+            b'"' => {
+                let start = self.pos;
+                self.pos = scan_string(self.source, start).map_err(|_| PacketParseError)?;
+                Token::String(&self.source[start + 1..self.pos - 1])
+            }
+            _ => return Err(PacketParseError),
+        };
+
+        // atoms always live inside a list
+        if self.depth == 0 && !self.root_closed {
+            return Err(PacketParseError);
+        }
+
+        Ok(Some(token))
+    }
+
+    /// Reads the rest of the string: Ok only if every bracket was closed
+    pub(crate) fn finish(&mut self) -> Result<(), PacketParseError> {
+        while self.next_token()?.is_some() {}
+
+        if self.root_closed {
+            Ok(())
+        } else {
+            Err(PacketParseError)
+        }
+    }
+
+    // i32 without going through a String: digits are accumulated as a negative number
+    // so i32::MIN fits, and overflow is an error instead of wrapping
+    fn integer(&mut self) -> Result<i32, PacketParseError> {
+        let negative = self.bytes[self.pos] == b'-';
+        if negative {
+            self.pos += 1;
+        }
+
+        let start = self.pos;
+        let mut n: i32 = 0;
+
+        while let Some(c @ b'0'..=b'9') = self.bytes.get(self.pos) {
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_sub((c - b'0') as i32))
+                .ok_or(PacketParseError)?;
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            return Err(PacketParseError);
+        }
+
+        if negative {
+            Ok(n)
+        } else {
+            n.checked_neg().ok_or(PacketParseError)
+        }
+    }
+
+    fn expect_token(&mut self) -> Result<Token<'a>, PacketParseError> {
+        self.next_token()?.ok_or(PacketParseError)
+    }
+}
+
+// both cursors have just read the [ of the lists being compared
+fn compare_lists(left: &mut Tokens, right: &mut Tokens) -> Result<Ordering, PacketParseError> {
+    loop {
+        let left_token = left.expect_token()?;
+        let right_token = right.expect_token()?;
+
+        match (left_token, right_token) {
+            (Token::Close, Token::Close) => return Ok(Ordering::Equal),
+            (Token::Close, _) => return Ok(Ordering::Less),
+            (_, Token::Close) => return Ok(Ordering::Greater),
+            (left_token, right_token) => {
+                let ordering = compare_values(left, left_token, right, right_token)?;
+
+                if ordering != Ordering::Equal {
+                    return Ok(ordering);
+                }
+            }
+        }
+    }
+}
+
+fn compare_values(
+    left: &mut Tokens,
+    left_token: Token,
+    right: &mut Tokens,
+    right_token: Token,
+) -> Result<Ordering, PacketParseError> {
+    match (left_token, right_token) {
+        (Token::Close, _) | (_, Token::Close) => Err(PacketParseError),
+        (Token::Open, Token::Open) => compare_lists(left, right),
+        (atom, Token::Open) => compare_atom_with_list(atom, right),
+        (Token::Open, atom) => Ok(compare_atom_with_list(atom, left)?.reverse()),
+        (atom_1, atom_2) => Ok(compare_atoms(atom_1, atom_2)),
+    }
+}
+
+// same as Ord for PacketDatum: integers by value, strings by their (unescaped) chars,
+// and integers before strings
+pub(super) fn compare_atoms(left: Token, right: Token) -> Ordering {
+    match (left, right) {
+        (Token::Integer(i1), Token::Integer(i2)) => i1.cmp(&i2),
+        (Token::String(s1), Token::String(s2)) => decoded_chars(s1).cmp(decoded_chars(s2)),
+        (Token::Integer(_), Token::String(_)) => Ordering::Less,
+        (Token::String(_), Token::Integer(_)) => Ordering::Greater,
+        _ => unreachable!("brackets aren't atoms"),
+    }
+}
+
+// [atom] vs the list the cursor has just opened
+fn compare_atom_with_list(atom: Token, list: &mut Tokens) -> Result<Ordering, PacketParseError> {
+    let ordering = match list.expect_token()? {
+        Token::Close => return Ok(Ordering::Greater),
+        Token::Open => compare_atom_with_list(atom, list)?,
+        atom_2 => compare_atoms(atom, atom_2),
+    };
+
+    if ordering != Ordering::Equal {
+        return Ok(ordering);
+    }
+
+    match list.expect_token()? {
+        Token::Close => Ok(Ordering::Equal),
+        _ => Ok(Ordering::Less),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_str, compare_validated};
+    use crate::datum::tests::EXAMPLE_PACKETS;
+    use crate::datum::PacketDatum;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn advent_of_code_pairs() {
+        let expected = [
+            Ordering::Less,
+            Ordering::Less,
+            Ordering::Greater,
+            Ordering::Less,
+            Ordering::Greater,
+            Ordering::Less,
+            Ordering::Greater,
+            Ordering::Greater,
+        ];
+
+        for (pair, expected) in EXAMPLE_PACKETS.chunks(2).zip(expected) {
+            assert!(compare_str(pair[0], pair[1]).unwrap() == expected);
+        }
+    }
+
+    #[test]
+    // [3] == [[3]] == [[[3]]], but [[3],1] > [[[3]]]
+    fn integer_promotion() {
+        assert!(compare_str("[3]", "[[[3]]]").unwrap() == Ordering::Equal);
+        assert!(compare_str("[[[3]]]", "[3]").unwrap() == Ordering::Equal);
+        assert!(compare_str("[[3],1]", "[[[3]]]").unwrap() == Ordering::Greater);
+        assert!(compare_str("[3]", "[[[3,0]]]").unwrap() == Ordering::Less);
+        assert!(compare_str("[3]", "[[[]]]").unwrap() == Ordering::Greater);
+        assert!(compare_str("[-3]", "[[-2]]").unwrap() == Ordering::Less);
+    }
+
+    #[test]
+    // strings compare by what the escapes stand for, not by how they're written
+    fn strings() {
+        assert!(compare_str(r#"["\u0041"]"#, r#"["A"]"#).unwrap() == Ordering::Equal);
+        assert!(compare_str(r#"["\""]"#, r#"["!"]"#).unwrap() == Ordering::Greater);
+        assert!(compare_str(r#"["ab"]"#, r#"["a"]"#).unwrap() == Ordering::Greater);
+        assert!(compare_str(r#"["\ud83d\ude00"]"#, r#"["\uffff"]"#).unwrap() == Ordering::Greater);
+        // integers before strings, and strings are promoted like integers
+        assert!(compare_str(r#"[99]"#, r#"[""]"#).unwrap() == Ordering::Less);
+        assert!(compare_str(r#"["a"]"#, r#"[[["a"]]]"#).unwrap() == Ordering::Equal);
+        assert!(compare_str(r#"["a"]"#, r#"[["a",1]]"#).unwrap() == Ordering::Less);
+        // brackets inside strings are text
+        assert!(compare_str(r#"["]",1]"#, r#"["]",[1]]"#).unwrap() == Ordering::Equal);
+    }
+
+    #[test]
+    fn whitespace() {
+        assert!(compare_str(" [ 1, [2 ,3] ]\n", "[1,[2,3]]").unwrap() == Ordering::Equal);
+    }
+
+    #[test]
+    // malformed text is an error even after the ordering is already decided
+    fn invalid() {
+        let valid = "[1,2]";
+
+        for invalid in [
+            "",
+            "1",
+            "[1,2",
+            "[1,2]]",
+            "[1,2][3]",
+            "[1,a]",
+            "[-]",
+            "[1,2,[3]",
+            "]",
+            "[99999999999]",
+            r#"["a]"#,
+            r#"["\x"]"#,
+            r#""a""#,
+        ] {
+            assert!(compare_str(invalid, valid).is_err());
+            assert!(compare_str(valid, invalid).is_err());
+        }
+
+        assert!(compare_str("[0,[", "[1]").is_err());
+    }
+
+    #[test]
+    fn extreme_integers() {
+        assert!(compare_str("[-2147483648]", "[2147483647]").unwrap() == Ordering::Less);
+        assert!(compare_str("[2147483648]", "[1]").is_err());
+    }
+
+    // Differential test against the tree based ordering over pseudo random packets
+    mod differential {
+        use super::*;
+        use crate::generate::{Distribution, PacketGenerator};
+
+        #[test]
+        fn matches_tree_ordering() {
+            let mut generator = PacketGenerator::new(0x9e3779b97f4a7c15)
+                .depth(Distribution::Uniform(1..=5))
+                .list_length(Distribution::Uniform(0..=3))
+                .integers(-1..=3)
+                .labels(&["", "a", "b", "a\""])
+                .string_probability(0.25);
+
+            // every other packet spells "a" with an escape, the same string either way
+            let packets: Vec<String> = (0..300)
+                .map(|idx| match idx % 2 {
+                    0 => generator.generate().to_string(),
+                    _ => generator
+                        .generate()
+                        .to_string()
+                        .replace("\"a\"", "\"\\u0061\""),
+                })
+                .chain(EXAMPLE_PACKETS.iter().map(|s| s.to_string()))
+                .collect();
+
+            let parsed: Vec<PacketDatum> = packets.iter().map(|s| s.parse().unwrap()).collect();
+
+            for (s1, p1) in packets.iter().zip(&parsed) {
+                for (s2, p2) in packets.iter().zip(&parsed) {
+                    assert!(compare_str(s1, s2).unwrap() == p1.cmp(p2));
+                    assert!(compare_validated(s1, s2) == p1.cmp(p2));
+                }
+            }
+        }
+    }
+}
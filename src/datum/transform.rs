@@ -0,0 +1,309 @@
+use super::PacketDatum;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// cmp promotes atoms (integers and strings) to lists, so 3, [3] and [[3]] all compare Equal.
+// The same logical packet can be written many ways, and these transforms rewrite packets
+// into other shapes. Some of them keep the packet Equal under Ord, some don't:
+//
+// - normalize:           keeps Ord. a.cmp(b) == Equal exactly when a.normalize() == b.normalize()
+// - collapse_singletons: keeps Ord only for singleton runs that end in an atom
+// - flatten:             does NOT keep Ord. the list structure is part of the comparison
+// - remove_empty_lists:  does NOT keep Ord. [] is smaller than everything, removing it moves packets around
+//
+// Every transform builds a brand new tree, so shared children are never mutated.
+// The root of the result is always a List so it's still a valid packet.
+
+/// Transforms Module
+///
+/// Contains:
+/// - normalize: unique canonical representative under Ord's equivalence
+/// - flatten: every atom in order inside one List
+/// - remove_empty_lists: drops [] everywhere
+/// - collapse_singletons: cuts runs of singleton lists [[[x]]] down to a max depth
+impl PacketDatum {
+    /// Unique representative of everything that compares Equal to this packet.
+    ///
+    /// Anything nested that's interchangeable with an atom (Integer or String) becomes that atom,
+    /// and the root always becomes a List: 3, [3], [[3]] all normalize to [3].
+    ///
+    /// Keeps Ord: a.cmp(&b) == Equal exactly when a.normalize() == b.normalize(),
+    /// and a.normalize().cmp(&a) is always Equal.
+    pub fn normalize(&self) -> PacketDatum {
+        match self.normalize_inner() {
+            PacketDatum::List(l) => PacketDatum::List(l),
+            atom => PacketDatum::List(vec![rc(atom)]),
+        }
+    }
+
+    fn normalize_inner(&self) -> PacketDatum {
+        if let Some(atom) = self.promoted_atom() {
+            return atom;
+        }
+
+        match self {
+            PacketDatum::List(l) => PacketDatum::List(
+                l.iter()
+                    .map(|packet_datum| rc(packet_datum.borrow().normalize_inner()))
+                    .collect(),
+            ),
+            atom => atom.clone(),
+        }
+    }
+
+    /// Every atom (Integer or String) in the packet, in order, in a single List:
+    /// [1,[2,["a"]],[]] becomes [1,2,"a"].
+    ///
+    /// Does NOT keep Ord: the list structure is part of the comparison.
+    /// [[1,2],3] > [[1],2,4] since [1,2] is longer than [1], but flattened [1,2,3] < [1,2,4].
+    pub fn flatten(&self) -> PacketDatum {
+        let mut atoms = vec![];
+        self.collect_atoms(&mut atoms);
+
+        PacketDatum::List(atoms)
+    }
+
+    fn collect_atoms(&self, atoms: &mut Vec<Rc<RefCell<PacketDatum>>>) {
+        match self {
+            PacketDatum::List(l) => {
+                for packet_datum in l {
+                    packet_datum.borrow().collect_atoms(atoms);
+                }
+            }
+            atom => atoms.push(rc(atom.clone())),
+        }
+    }
+
+    /// Drops every empty list. Lists that only held empty lists become empty and get dropped too,
+    /// so [[[]],1,[]] becomes [1]. The root is kept even if it ends up empty.
+    ///
+    /// Does NOT keep Ord: [] is smaller than any atom or non-empty list.
+    /// [[],1] < [0] since [] ran out of items first, but after removal [1] > [0].
+    pub fn remove_empty_lists(&self) -> PacketDatum {
+        match self.remove_empty_lists_inner() {
+            Some(packet_datum) => packet_datum,
+            None => PacketDatum::List(vec![]),
+        }
+    }
+
+    // None means this datum is (or became) an empty list
+    fn remove_empty_lists_inner(&self) -> Option<PacketDatum> {
+        match self {
+            PacketDatum::Integer(i) => Some(PacketDatum::Integer(*i)),
+            PacketDatum::String(s) => Some(PacketDatum::String(s.clone())),
+            PacketDatum::List(l) => {
+                let children: Vec<Rc<RefCell<PacketDatum>>> = l
+                    .iter()
+                    .filter_map(|packet_datum| packet_datum.borrow().remove_empty_lists_inner())
+                    .map(rc)
+                    .collect();
+
+                if children.is_empty() {
+                    None
+                } else {
+                    Some(PacketDatum::List(children))
+                }
+            }
+        }
+    }
+
+    /// Cuts every run of nested singleton lists down to at most `max_depth` levels.
+    ///
+    /// With max_depth 1: [[[[3]]],[[[1,2]]]] becomes [[3],[[1,2]]].
+    /// The root counts as a level, and it stays a List even with max_depth 0.
+    ///
+    /// Keeps Ord for runs that end in an atom, since [[3]] and 3 compare Equal.
+    /// Does NOT keep Ord for runs that end in an empty or longer list: [[[1,2]]] > [[1,2]],
+    /// so collapsing those makes packets that were greater compare Equal.
+    pub fn collapse_singletons(&self, max_depth: usize) -> PacketDatum {
+        match self.collapse_singletons_inner(max_depth) {
+            PacketDatum::List(l) => PacketDatum::List(l),
+            atom => PacketDatum::List(vec![rc(atom)]),
+        }
+    }
+
+    fn collapse_singletons_inner(&self, max_depth: usize) -> PacketDatum {
+        // walk down the run of singleton lists
+        let mut levels = 0;
+        let mut inner = match self {
+            PacketDatum::List(l) if l.len() == 1 => Rc::clone(&l[0]),
+            PacketDatum::List(l) => return collapse_children(l, max_depth),
+            atom => return atom.clone(),
+        };
+        levels += 1;
+
+        loop {
+            let next = match &*inner.borrow() {
+                PacketDatum::List(l) if l.len() == 1 => Rc::clone(&l[0]),
+                _ => break,
+            };
+            inner = next;
+            levels += 1;
+        }
+
+        let inner = inner.borrow();
+        let mut collapsed = match &*inner {
+            PacketDatum::List(l) => collapse_children(l, max_depth),
+            atom => atom.clone(),
+        };
+
+        for _ in 0..levels.min(max_depth) {
+            collapsed = PacketDatum::List(vec![rc(collapsed)]);
+        }
+
+        collapsed
+    }
+}
+
+fn collapse_children(l: &[Rc<RefCell<PacketDatum>>], max_depth: usize) -> PacketDatum {
+    PacketDatum::List(
+        l.iter()
+            .map(|packet_datum| rc(packet_datum.borrow().collapse_singletons_inner(max_depth)))
+            .collect(),
+    )
+}
+
+fn rc(packet_datum: PacketDatum) -> Rc<RefCell<PacketDatum>> {
+    Rc::new(RefCell::new(packet_datum))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datum::tests::parse;
+    use crate::datum::PacketDatum as pd;
+    use std::cmp::Ordering;
+
+    mod normalize {
+        use super::*;
+
+        #[test]
+        // 3, [3], [[3]] => [3]
+        fn promoted_integers() {
+            let expected = parse("[3]");
+
+            assert!(pd::Integer(3).normalize() == expected);
+            assert!(parse("[3]").normalize() == expected);
+            assert!(parse("[[3]]").normalize() == expected);
+            assert!(parse("[[[3]]]").normalize() == expected);
+        }
+
+        #[test]
+        // strings are promoted like integers: [["a"],[[2]]] => ["a",2]
+        fn promoted_strings() {
+            assert!(parse(r#"[["a"],[[2]]]"#).normalize() == parse(r#"["a",2]"#));
+            assert!(pd::String("a".to_string()).normalize() == parse(r#"["a"]"#));
+        }
+
+        #[test]
+        // [[1],[[2],3]] => [1,[2,3]]
+        fn nested() {
+            let normalized = parse("[[1],[[2],3]]").normalize();
+
+            assert!(normalized == parse("[1,[2,3]]"));
+        }
+
+        #[test]
+        // empty lists aren't interchangeable with anything else
+        fn empty_lists_stay() {
+            assert!(parse("[]").normalize() == parse("[]"));
+            assert!(parse("[[]]").normalize() == parse("[[]]"));
+            assert!(parse("[[[]],[[4]]]").normalize() == parse("[[[]],4]"));
+        }
+
+        #[test]
+        fn equal_under_ord_iff_normalized_equal() {
+            let packets = [
+                "[1,1,3,1,1]",
+                "[[1],[2,3,4]]",
+                "[[1],4]",
+                "[1,[[2],[3],4]]",
+                "[[[1]],[2,3,4]]",
+                "[[4,4],4,4]",
+                "[[[4],[4]],[4],4]",
+                "[]",
+                "[[]]",
+                "[[[]]]",
+                "[3]",
+                "[[3]]",
+                r#"["a"]"#,
+                r#"[[["a"]]]"#,
+                r#"[["a",1]]"#,
+                r#"["a",[1]]"#,
+            ]
+            .map(parse);
+
+            for a in &packets {
+                assert!(a.normalize().cmp(a) == Ordering::Equal);
+
+                for b in &packets {
+                    let equal = a.cmp(b) == Ordering::Equal;
+                    assert!(equal == (a.normalize() == b.normalize()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    // [1,[2,[3,[4,[5,6,7]]]],8,9] => [1,2,3,4,5,6,7,8,9]
+    fn flatten() {
+        let flattened = parse("[1,[2,[3,[4,[5,6,7]]]],8,9]").flatten();
+
+        assert!(flattened == pd::int_list(vec![1, 2, 3, 4, 5, 6, 7, 8, 9]));
+        assert!(parse("[[[]],[]]").flatten() == parse("[]"));
+        assert!(parse(r#"[["a",[1]],"b"]"#).flatten() == parse(r#"["a",1,"b"]"#));
+
+        // [[1,2],3] > [[1],2,4] but [1,2,3] < [1,2,4]
+        let packet_1 = parse("[[1,2],3]");
+        let packet_2 = parse("[[1],2,4]");
+        assert!(packet_1 > packet_2);
+        assert!(packet_1.flatten() < packet_2.flatten());
+    }
+
+    #[test]
+    fn remove_empty_lists() {
+        assert!(parse("[[[]],1,[]]").remove_empty_lists() == parse("[1]"));
+        assert!(parse("[[[]]]").remove_empty_lists() == parse("[]"));
+        assert!(parse("[[1,[]],[[],[2]]]").remove_empty_lists() == parse("[[1],[[2]]]"));
+
+        // [[],1] < [0] but [1] > [0]
+        let packet_1 = parse("[[],1]");
+        let packet_2 = parse("[0]");
+        assert!(packet_1 < packet_2);
+        assert!(packet_1.remove_empty_lists() > packet_2.remove_empty_lists());
+    }
+
+    mod collapse_singletons {
+        use super::*;
+
+        #[test]
+        fn max_depth_1() {
+            let collapsed = parse("[[[[3]]],[[[1,2]]]]").collapse_singletons(1);
+
+            assert!(collapsed == parse("[[3],[[1,2]]]"));
+        }
+
+        #[test]
+        fn max_depth_0() {
+            assert!(parse("[[[[3]]],[[[1,2]]]]").collapse_singletons(0) == parse("[3,[1,2]]"));
+            // root stays a list
+            assert!(parse("[[[3]]]").collapse_singletons(0) == parse("[3]"));
+            assert!(parse("[[[]]]").collapse_singletons(0) == parse("[]"));
+        }
+
+        #[test]
+        fn deep_enough_is_unchanged() {
+            let packet = parse("[1,[2,[3,[4,[5,6,7]]]],8,9]");
+
+            assert!(packet.collapse_singletons(3) == packet);
+            assert!(parse("[[[[3]]]]").collapse_singletons(4) == parse("[[[[3]]]]"));
+        }
+
+        #[test]
+        // collapsing a run ending in an Integer keeps the packet Equal
+        fn integer_runs_keep_ord() {
+            let packet = parse("[[[[3]]],[[5]],1]");
+
+            assert!(packet.collapse_singletons(0).cmp(&packet) == Ordering::Equal);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use crate::datum::{compare_validated, validate_str, PacketDatum};
+use crate::input::{self, InputError};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::io::BufRead;
+
+// Part 2 sorts every packet together with the divider packets, then multiplies the
+// 1-based positions of the dividers.
+//
+// Sorting isn't needed for that: a divider's position is
+//     1 + (packets before it) + (other dividers before it)
+// and "packets before it" is just a count, so one pass over the packets is enough
+// and they never have to be held in memory at the same time.
+//
+// Reading lines, a packet doesn't even have to be parsed: each line is validated once
+// and then compared with the dividers' text directly, so no PacketDatum is built per line.
+//
+// Dividers are treated as if they were appended after the packets and stable sorted:
+// packets that compare Equal to a divider come before it,
+// and Equal dividers keep the order they were given in.
+
+/// Dividers Module:
+/// 1-based positions of divider packets without sorting.
+///
+/// Contains:
+/// - positions_of: counts over any iterator of PacketDatums
+/// - positions_of_lines: counts straight from puzzle input text, one packet line at a time
+/// - decoder_key: product of the divider positions
+pub fn positions_of<I>(dividers: &[PacketDatum], packets: I) -> Vec<usize>
+where
+    I: IntoIterator,
+    I::Item: Borrow<PacketDatum>,
+{
+    let order = sorted_divider_order(dividers);
+
+    // packets_before[k]: packets that come before the k-th smallest divider and after the (k-1)th
+    let mut packets_before = vec![0; dividers.len() + 1];
+
+    for packet in packets {
+        let packet = packet.borrow();
+        // first divider (in sorted order) the packet doesn't come after
+        let k = order.partition_point(|&d| packet.cmp(&dividers[d]) == Ordering::Greater);
+        packets_before[k] += 1;
+    }
+
+    positions(&order, &packets_before)
+}
+
+/// Same as positions_of, reading one packet per line and skipping blank lines.
+///
+/// Lines are compared as text, without building PacketDatums. They're validated with
+/// validate_str, which accepts exactly the lines input::read_packets parses.
+/// Only one line is in memory at a time, so this works on inputs bigger than RAM.
+pub fn positions_of_lines<R: BufRead>(
+    dividers: &[PacketDatum],
+    reader: R,
+) -> Result<Vec<usize>, InputError> {
+    let order = sorted_divider_order(dividers);
+    let divider_lines: Vec<String> = dividers.iter().map(|d| d.to_string()).collect();
+    let mut packets_before = vec![0; dividers.len() + 1];
+
+    for line in input::packet_lines(reader) {
+        let (line_number, line) = line?;
+
+        if validate_str(&line).is_err() {
+            return Err(InputError::Parse { line: line_number });
+        }
+
+        let k = order
+            .partition_point(|&d| compare_validated(&line, &divider_lines[d]) == Ordering::Greater);
+        packets_before[k] += 1;
+    }
+
+    Ok(positions(&order, &packets_before))
+}
+
+/// Product of every divider's position, the answer to part 2.
+pub fn decoder_key(positions: &[usize]) -> usize {
+    positions.iter().product()
+}
+
+// divider indices in sorted order, Equal dividers keep their original order
+fn sorted_divider_order(dividers: &[PacketDatum]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..dividers.len()).collect();
+    order.sort_by(|&d1, &d2| dividers[d1].cmp(&dividers[d2]));
+
+    order
+}
+
+fn positions(order: &[usize], packets_before: &[usize]) -> Vec<usize> {
+    let mut positions = vec![0; order.len()];
+    let mut packets_so_far = 0;
+
+    for (k, &d) in order.iter().enumerate() {
+        packets_so_far += packets_before[k];
+        // k dividers come before this one
+        positions[d] = 1 + packets_so_far + k;
+    }
+
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_INPUT: &str = "[1,1,3,1,1]
+[1,1,5,1,1]
+
+[[1],[2,3,4]]
+[[1],4]
+
+[9]
+[[8,7,6]]
+
+[[4,4],4,4]
+[[4,4],4,4,4]
+
+[7,7,7,7]
+[7,7,7]
+
+[]
+[3]
+
+[[[]]]
+[[]]
+
+[1,[2,[3,[4,[5,6,7]]]],8,9]
+[1,[2,[3,[4,[5,6,0]]]],8,9]
+";
+
+    fn dividers() -> Vec<PacketDatum> {
+        vec!["[[2]]".parse().unwrap(), "[[6]]".parse().unwrap()]
+    }
+
+    #[test]
+    fn advent_of_code_example() {
+        let packets = input::read_packets(EXAMPLE_INPUT.as_bytes()).unwrap();
+
+        let positions = positions_of(&dividers(), &packets);
+
+        assert!(positions == vec![10, 14]);
+        assert!(decoder_key(&positions) == 140);
+    }
+
+    #[test]
+    fn from_lines() {
+        let positions = positions_of_lines(&dividers(), EXAMPLE_INPUT.as_bytes()).unwrap();
+
+        assert!(positions == vec![10, 14]);
+
+        let dividers: Vec<PacketDatum> = ["[3]", "[[2]]", "[]", "[[3]]", "[10]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let packets = input::read_packets(EXAMPLE_INPUT.as_bytes()).unwrap();
+        assert!(
+            positions_of_lines(&dividers, EXAMPLE_INPUT.as_bytes()).unwrap()
+                == positions_of(&dividers, &packets)
+        );
+    }
+
+    #[test]
+    // both ways of getting the positions reject the same lines, on the same line number
+    fn invalid_line() {
+        for bad_line in ["[[2],3", "[1,a]", "[1][2]", "[1]]", "x"] {
+            let input = format!("[1]\n\n{}\n", bad_line);
+
+            match positions_of_lines(&dividers(), input.as_bytes()) {
+                Err(InputError::Parse { line }) => assert!(line == 3),
+                _ => panic!("expected a parse error"),
+            }
+
+            match input::read_packets(input.as_bytes()) {
+                Err(InputError::Parse { line }) => assert!(line == 3),
+                _ => panic!("expected a parse error"),
+            }
+        }
+    }
+
+    #[test]
+    // dividers given out of order still get their own positions
+    fn unsorted_dividers() {
+        let packets = input::read_packets(EXAMPLE_INPUT.as_bytes()).unwrap();
+        let dividers: Vec<PacketDatum> = vec!["[[6]]".parse().unwrap(), "[[2]]".parse().unwrap()];
+
+        assert!(positions_of(&dividers, packets) == vec![14, 10]);
+    }
+
+    #[test]
+    // matches sorting everything together
+    fn matches_sort() {
+        let packets = input::read_packets(EXAMPLE_INPUT.as_bytes()).unwrap();
+        let dividers: Vec<PacketDatum> = ["[3]", "[[2]]", "[]", "[[3]]", "[10]"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        let mut all: Vec<(PacketDatum, Option<usize>)> =
+            packets.iter().cloned().map(|p| (p, None)).collect();
+        all.extend(
+            dividers
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(d, p)| (p, Some(d))),
+        );
+        all.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+
+        let mut expected = vec![0; dividers.len()];
+        for (idx, (_, d)) in all.iter().enumerate() {
+            if let Some(d) = d {
+                expected[*d] = idx + 1;
+            }
+        }
+
+        assert!(positions_of(&dividers, &packets) == expected);
+    }
+
+    #[test]
+    fn no_packets() {
+        let packets: Vec<PacketDatum> = vec![];
+
+        assert!(positions_of(&dividers(), &packets) == vec![1, 2]);
+    }
+}
@@ -0,0 +1,458 @@
+use crate::datum::{compare_validated, validate_str};
+use crate::input::{self, InputError};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+// External merge sort for packet files that don't fit in memory.
+//
+// 1. Read packet lines until the memory budget is used up: that's a run.
+// 2. Sort the run by comparing the text (same rules as cmp, no PacketDatums needed)
+//    and spill it to a temporary file, one packet per line.
+// 3. Once the input is used up, k-way merge the runs: a min heap holds the next line of every run,
+//    the smallest one is written out and replaced by the next line of the run it came from.
+//
+// Every run being merged costs a read buffer plus its current line, so a merge only takes
+// as many runs as the memory budget pays for (the fan in, at least 2, at most max_fan_in so
+// there aren't thousands of open files). With more runs than that, groups of consecutive runs
+// are merged into bigger intermediate runs first, pass after pass, until one merge is left.
+//
+// Runs are sorted with a stable sort and the merge breaks ties by run number,
+// and intermediate runs are merged from consecutive runs,
+// so packets that compare Equal come out in the order they went in,
+// exactly like a stable in memory sort.
+//
+// Input that fits in a single run is sorted in memory and never touches the disk.
+
+/// External Module:
+/// ExternalSort sorts a stream of packet lines with bounded memory.
+///
+/// ex: ExternalSort::new().memory_budget(256 << 20).sort(reader, writer)
+pub struct ExternalSort {
+    memory_budget: usize,
+    max_fan_in: usize,
+    temp_dir: PathBuf,
+}
+
+/// What happened during a sort.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SortStats {
+    pub packets: usize,
+    /// number of runs spilled to temporary files, 0 if everything fit in memory
+    pub spilled_runs: usize,
+    /// merges over the runs, more than 1 if there were more runs than the fan in
+    pub merge_passes: usize,
+}
+
+impl Default for ExternalSort {
+    fn default() -> Self {
+        ExternalSort::new()
+    }
+}
+
+impl ExternalSort {
+    /// 64 MiB memory budget, at most 256 runs merged at once,
+    /// runs spill to the system temp directory.
+    pub fn new() -> Self {
+        ExternalSort {
+            memory_budget: 64 << 20,
+            max_fan_in: 256,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Roughly how many bytes are held in memory at once:
+    /// packet lines while building runs, read buffers and lines while merging them.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// The most runs (open files) merged at once. The memory budget can lower it further,
+    /// but never below 2.
+    pub fn max_fan_in(mut self, runs: usize) -> Self {
+        self.max_fan_in = runs.max(2);
+        self
+    }
+
+    /// Where the sorted runs are spilled. They're deleted once the sort is done.
+    pub fn temp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.temp_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Reads one packet per line (blank lines skipped), writes them back out sorted, one per line.
+    pub fn sort<R: BufRead, W: Write>(&self, input: R, output: W) -> Result<SortStats, InputError> {
+        let mut output = BufWriter::new(output);
+        let mut runs: Vec<RunFile> = vec![];
+        let mut run: Vec<String> = vec![];
+        let mut run_bytes = 0;
+        let mut longest_line = 0;
+        let mut packets = 0;
+
+        for line in input::packet_lines(input) {
+            let (line_number, line) = line?;
+            let line = line.trim().to_string();
+
+            if validate_str(&line).is_err() {
+                return Err(InputError::Parse { line: line_number });
+            }
+
+            run_bytes += line.len() + LINE_OVERHEAD;
+            longest_line = longest_line.max(line.len());
+            run.push(line);
+            packets += 1;
+
+            if run_bytes >= self.memory_budget {
+                runs.push(self.spill(&mut run)?);
+                run_bytes = 0;
+            }
+        }
+
+        if runs.is_empty() {
+            sort_run(&mut run);
+            write_lines(&mut output, run.iter())?;
+            output.flush()?;
+
+            return Ok(SortStats {
+                packets,
+                spilled_runs: 0,
+                merge_passes: 0,
+            });
+        }
+
+        if !run.is_empty() {
+            runs.push(self.spill(&mut run)?);
+        }
+
+        let spilled_runs = runs.len();
+        let merge_passes = self.merge_runs(runs, self.fan_in(longest_line), &mut output)?;
+        output.flush()?;
+
+        Ok(SortStats {
+            packets,
+            spilled_runs,
+            merge_passes,
+        })
+    }
+
+    // runs the budget can merge at once, each one a read buffer and its longest possible line
+    fn fan_in(&self, longest_line: usize) -> usize {
+        let per_run = MERGE_BUFFER + longest_line + LINE_OVERHEAD;
+
+        (self.memory_budget / per_run).clamp(2, self.max_fan_in)
+    }
+
+    // merges groups of fan_in consecutive runs into intermediate runs until there are few enough
+    // left for one last merge into output, returns the number of passes
+    fn merge_runs<W: Write>(
+        &self,
+        mut runs: Vec<RunFile>,
+        fan_in: usize,
+        output: &mut W,
+    ) -> Result<usize, InputError> {
+        let mut passes = 1;
+
+        while runs.len() > fan_in {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(fan_in));
+
+            for group in runs.chunks(fan_in) {
+                let (run_file, file) = RunFile::create(&self.temp_dir)?;
+                let mut writer = BufWriter::new(file);
+                merge(group, &mut writer)?;
+                writer.flush()?;
+
+                merged.push(run_file);
+            }
+
+            // the runs that were merged are dropped, which deletes them
+            runs = merged;
+            passes += 1;
+        }
+
+        merge(&runs, output)?;
+
+        Ok(passes)
+    }
+
+    fn spill(&self, run: &mut Vec<String>) -> Result<RunFile, InputError> {
+        sort_run(run);
+
+        let (run_file, file) = RunFile::create(&self.temp_dir)?;
+        let mut writer = BufWriter::new(file);
+        write_lines(&mut writer, run.iter())?;
+        writer.flush()?;
+
+        run.clear();
+
+        Ok(run_file)
+    }
+}
+
+// Vec<String> bookkeeping per line on top of the line's own bytes
+const LINE_OVERHEAD: usize = std::mem::size_of::<String>();
+
+// read buffer per run being merged
+const MERGE_BUFFER: usize = 8 << 10;
+
+// lines are validated before they get here
+fn sort_run(run: &mut [String]) {
+    run.sort_by(|line_1, line_2| compare_validated(line_1, line_2));
+}
+
+fn write_lines<'a, W, I>(output: &mut W, lines: I) -> Result<(), InputError>
+where
+    W: Write,
+    I: Iterator<Item = &'a String>,
+{
+    for line in lines {
+        output.write_all(line.as_bytes())?;
+        output.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn merge<W: Write>(runs: &[RunFile], output: &mut W) -> Result<(), InputError> {
+    let mut readers = vec![];
+    let mut heap = BinaryHeap::new();
+
+    for (run, run_file) in runs.iter().enumerate() {
+        let mut reader =
+            BufReader::with_capacity(MERGE_BUFFER, File::open(&run_file.path)?).lines();
+
+        if let Some(line) = reader.next() {
+            heap.push(HeapEntry { line: line?, run });
+        }
+
+        readers.push(reader);
+    }
+
+    while let Some(HeapEntry { line, run }) = heap.pop() {
+        output.write_all(line.as_bytes())?;
+        output.write_all(b"\n")?;
+
+        if let Some(line) = readers[run].next() {
+            heap.push(HeapEntry { line: line?, run });
+        }
+    }
+
+    Ok(())
+}
+
+// BinaryHeap is a max heap, so the ordering is reversed to pop the smallest line first.
+// Ties go to the earlier run to keep the sort stable.
+// Every line in a run was validated before it was spilled, so it isn't checked again here.
+struct HeapEntry {
+    line: String,
+    run: usize,
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_validated(&self.line, &other.line)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+// A spilled run, deleted when dropped (including when the sort fails part way)
+struct RunFile {
+    path: PathBuf,
+}
+
+static RUN_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl RunFile {
+    // The temp dir is shared, so the name could already be taken (or planted as a symlink):
+    // create_new never opens an existing file, it fails and the next name is tried instead.
+    fn create(dir: &Path) -> Result<(RunFile, File), InputError> {
+        loop {
+            let id = RUN_FILES.fetch_add(1, AtomicOrdering::Relaxed);
+            let path = dir.join(format!("packet-run-{}-{}", std::process::id(), id));
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((RunFile { path }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for RunFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::PacketDatum;
+    use crate::generate::test_input;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("packet-13-22-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn in_memory_sort(input: &str) -> Vec<PacketDatum> {
+        let mut packets = input::read_packets(input.as_bytes()).unwrap();
+        packets.sort();
+        packets
+    }
+
+    #[test]
+    fn spills_and_matches_in_memory_sort() {
+        let input = test_input(2, 1_000);
+        let dir = test_dir("spill");
+        let mut output = vec![];
+
+        let stats = ExternalSort::new()
+            .memory_budget(4 << 10)
+            .temp_dir(&dir)
+            .sort(input.as_bytes(), &mut output)
+            .unwrap();
+
+        assert!(stats.packets == 2_000);
+        assert!(stats.spilled_runs > 1);
+
+        let sorted = input::read_packets(output.as_slice()).unwrap();
+        assert!(sorted == in_memory_sort(&input));
+
+        // every run was cleaned up
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn fits_in_memory() {
+        let input = test_input(2, 100);
+        let mut output = vec![];
+
+        let stats = ExternalSort::new()
+            .sort(input.as_bytes(), &mut output)
+            .unwrap();
+
+        assert!(stats.spilled_runs == 0);
+        assert!(stats.merge_passes == 0);
+
+        let sorted = input::read_packets(output.as_slice()).unwrap();
+        assert!(sorted == in_memory_sort(&input));
+    }
+
+    #[test]
+    // a tiny budget only pays for merging 2 runs at once, so the runs are merged in passes
+    fn merges_in_passes() {
+        let input = test_input(2, 1_000);
+        let dir = test_dir("passes");
+        let mut output = vec![];
+
+        let stats = ExternalSort::new()
+            .memory_budget(1 << 10)
+            .temp_dir(&dir)
+            .sort(input.as_bytes(), &mut output)
+            .unwrap();
+
+        // fan in 2: ceil(log2(runs)) passes
+        assert!(stats.spilled_runs > 8);
+        assert!(stats.merge_passes == stats.spilled_runs.next_power_of_two().ilog2() as usize);
+
+        let sorted = input::read_packets(output.as_slice()).unwrap();
+        assert!(sorted == in_memory_sort(&input));
+
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    // the budget pays for 7 runs at once, max_fan_in lowers that to 3
+    fn max_fan_in() {
+        let input = test_input(2, 10_000);
+        let dir = test_dir("fan-in");
+        let mut output = vec![];
+
+        let stats = ExternalSort::new()
+            .memory_budget(64 << 10)
+            .max_fan_in(3)
+            .temp_dir(&dir)
+            .sort(input.as_bytes(), &mut output)
+            .unwrap();
+
+        // 14 runs -> 5 -> 2 -> output, 7 at once would have been 14 -> 2 -> output
+        assert!(stats.spilled_runs == 14);
+        assert!(stats.merge_passes == 3);
+
+        let sorted = input::read_packets(output.as_slice()).unwrap();
+        assert!(sorted == in_memory_sort(&input));
+
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    // a file already sitting on a run's name is skipped, not truncated
+    fn keeps_existing_files() {
+        let dir = test_dir("existing");
+        let next = RUN_FILES.load(AtomicOrdering::Relaxed);
+        // a few names ahead, other tests take run names at the same time
+        let planted: Vec<PathBuf> = (next..next + 64)
+            .map(|id| dir.join(format!("packet-run-{}-{}", std::process::id(), id)))
+            .collect();
+
+        for path in &planted {
+            fs::write(path, "planted").unwrap();
+        }
+
+        let (run_file, _) = RunFile::create(&dir).unwrap();
+        assert!(!planted.contains(&run_file.path));
+        drop(run_file);
+
+        for path in &planted {
+            assert!(fs::read_to_string(path).unwrap() == "planted");
+            fs::remove_file(path).unwrap();
+        }
+
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_line_cleans_up() {
+        let mut input = test_input(2, 250);
+        input.push_str("[1,[2]\n");
+        let dir = test_dir("invalid");
+
+        let result = ExternalSort::new()
+            .memory_budget(1 << 10)
+            .temp_dir(&dir)
+            .sort(input.as_bytes(), vec![]);
+
+        match result {
+            Err(InputError::Parse { line }) => assert!(line == 751),
+            _ => panic!("expected a parse error"),
+        }
+
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
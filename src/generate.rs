@@ -0,0 +1,587 @@
+use crate::datum::{scan_string, unescape, PacketDatum, PacketParseError};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+// Every packet is generated top down from one seeded xorshift generator,
+// so the same seed and settings always give the same packets, on every platform.
+//
+// Depth: each packet first draws its depth D from the depth distribution (the root list is 1).
+// Every list above depth D has one child forced to be a list, so one branch reaches
+// exactly depth D. Every other item is a list with probability list_probability
+// (while that stays within D), otherwise an atom: one of the labels with probability
+// string_probability, otherwise an integer. With the default string_probability of 0
+// no random numbers are drawn for it, so the packets for a seed are the same as without labels.
+//
+// Lists: a list that isn't needed to reach D is empty with probability empty_list_probability,
+// otherwise its length is drawn from the list length distribution.
+//
+// Templates: "[_,[*,3],*]" fixes the shape of the packet:
+// - _ is a random integer
+// - * is a random item (atom or list) drawn from the settings above
+// - brackets, integers and strings are copied as they are
+// With templates set, every packet uses one of them, picked uniformly.
+
+/// Generate Module:
+/// PacketGenerator: seeded random packets with controllable shapes, for load tests.
+///
+/// ex:
+/// let mut generator = PacketGenerator::new(42)
+///     .depth(Distribution::Uniform(1..=6))
+///     .list_length(Distribution::Weighted(vec![(0, 1), (3, 5), (10, 1)]))
+///     .integers(0..=100)
+///     .labels(&["temp", "pressure"])
+///     .string_probability(0.2)
+///     .empty_list_probability(0.1);
+///
+/// generator.write_input(1000, File::create("input.txt")?)?;
+#[derive(Clone, Debug)]
+pub struct PacketGenerator {
+    rng: Rng,
+    depth: Distribution,
+    list_length: Distribution,
+    integers: RangeInclusive<i32>,
+    labels: Vec<String>,
+    string_probability: f64,
+    list_probability: f64,
+    empty_list_probability: f64,
+    templates: Vec<Template>,
+}
+
+/// How a number (a depth, a list length) is picked.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distribution {
+    Constant(usize),
+    /// every value in the range equally likely
+    Uniform(RangeInclusive<usize>),
+    /// (value, weight) pairs: a value is picked weight / total weight of the time
+    Weighted(Vec<(usize, u32)>),
+}
+
+impl Distribution {
+    fn sample(&self, rng: &mut Rng) -> usize {
+        match self {
+            Distribution::Constant(n) => *n,
+            Distribution::Uniform(range) if range.is_empty() => *range.start(),
+            // the width of 0..=u64::MAX doesn't fit in a u64, but every u64 is in it
+            Distribution::Uniform(range) => {
+                match ((range.end() - range.start()) as u64).checked_add(1) {
+                    Some(width) => range.start() + rng.below(width) as usize,
+                    None => range.start() + rng.next() as usize,
+                }
+            }
+            Distribution::Weighted(weights) => {
+                let total: u64 = weights.iter().map(|(_, weight)| *weight as u64).sum();
+
+                if total == 0 {
+                    return weights.first().map_or(0, |(value, _)| *value);
+                }
+
+                let mut pick = rng.below(total);
+
+                for (value, weight) in weights {
+                    if pick < *weight as u64 {
+                        return *value;
+                    }
+
+                    pick -= *weight as u64;
+                }
+
+                unreachable!("pick is below the total weight")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Template {
+    List(Vec<Template>),
+    Integer(i32),
+    String(String),
+    RandomInteger,
+    RandomItem,
+}
+
+impl PacketGenerator {
+    /// Shaped like the puzzle input: depth 1..=4, lists of 0..=5 items, integers 0..=10,
+    /// no strings.
+    pub fn new(seed: u64) -> Self {
+        PacketGenerator {
+            rng: Rng::new(seed),
+            depth: Distribution::Uniform(1..=4),
+            list_length: Distribution::Uniform(0..=5),
+            integers: 0..=10,
+            labels: vec![],
+            string_probability: 0.0,
+            list_probability: 0.3,
+            empty_list_probability: 0.0,
+            templates: vec![],
+        }
+    }
+
+    /// Depth of each packet, the root list counts as 1. Depths of 0 are treated as 1.
+    pub fn depth(mut self, depth: Distribution) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Length of every list that isn't empty.
+    pub fn list_length(mut self, list_length: Distribution) -> Self {
+        self.list_length = list_length;
+        self
+    }
+
+    pub fn integers(mut self, integers: RangeInclusive<i32>) -> Self {
+        self.integers = integers;
+        self
+    }
+
+    /// Strings the string atoms are picked from, uniformly.
+    pub fn labels(mut self, labels: &[&str]) -> Self {
+        self.labels = labels.iter().map(|label| label.to_string()).collect();
+        self
+    }
+
+    /// Chance that an atom is one of the labels rather than an integer.
+    /// Without labels every atom is an integer.
+    pub fn string_probability(mut self, probability: f64) -> Self {
+        self.string_probability = probability;
+        self
+    }
+
+    /// Chance that an item is a list rather than an atom, where the depth allows one.
+    pub fn list_probability(mut self, probability: f64) -> Self {
+        self.list_probability = probability;
+        self
+    }
+
+    /// Chance that a list is empty, regardless of the list length distribution.
+    /// Lists needed to reach a packet's depth are never empty.
+    pub fn empty_list_probability(mut self, probability: f64) -> Self {
+        self.empty_list_probability = probability;
+        self
+    }
+
+    /// Adds a shape template, ex: "[_,[*,3],\"a\"]". _ is a random integer, * a random item.
+    pub fn template(mut self, template: &str) -> Result<Self, PacketParseError> {
+        self.templates.push(parse_template(template)?);
+        Ok(self)
+    }
+
+    pub fn generate(&mut self) -> PacketDatum {
+        if !self.templates.is_empty() {
+            let idx = self.rng.below(self.templates.len() as u64) as usize;
+            let template = self.templates[idx].clone();
+
+            return self.fill_template(&template, 0);
+        }
+
+        let depth = self.depth.sample(&mut self.rng).max(1);
+
+        self.list(1, depth, true)
+    }
+
+    /// Day 13 style input: pairs of packets, one per line, with a blank line after every pair.
+    pub fn write_input<W: Write>(&mut self, pairs: usize, mut output: W) -> io::Result<()> {
+        for _ in 0..pairs {
+            writeln!(output, "{}", self.generate())?;
+            writeln!(output, "{}", self.generate())?;
+            writeln!(output)?;
+        }
+
+        output.flush()
+    }
+
+    // a list at depth level; reach_depth: one of its children has to continue down to depth
+    fn list(&mut self, level: usize, depth: usize, reach_depth: bool) -> PacketDatum {
+        let must_go_deeper = reach_depth && level < depth;
+
+        if !must_go_deeper && self.rng.chance(self.empty_list_probability) {
+            return PacketDatum::List(vec![]);
+        }
+
+        let mut len = self.list_length.sample(&mut self.rng);
+        if must_go_deeper {
+            len = len.max(1);
+        }
+
+        let forced = must_go_deeper.then(|| self.rng.below(len as u64) as usize);
+
+        let items = (0..len)
+            .map(|idx| {
+                let item = if forced == Some(idx) {
+                    self.list(level + 1, depth, true)
+                } else {
+                    self.item(level, depth)
+                };
+
+                Rc::new(RefCell::new(item))
+            })
+            .collect();
+
+        PacketDatum::List(items)
+    }
+
+    // an item of a list at depth level
+    fn item(&mut self, level: usize, depth: usize) -> PacketDatum {
+        if level < depth && self.rng.chance(self.list_probability) {
+            self.list(level + 1, depth, false)
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> PacketDatum {
+        let string = self.string_probability > 0.0
+            && !self.labels.is_empty()
+            && self.rng.chance(self.string_probability);
+
+        if !string {
+            return self.integer();
+        }
+
+        let idx = self.rng.below(self.labels.len() as u64) as usize;
+
+        PacketDatum::String(self.labels[idx].clone())
+    }
+
+    fn integer(&mut self) -> PacketDatum {
+        let (start, end) = (*self.integers.start(), *self.integers.end());
+
+        if start > end {
+            return PacketDatum::Integer(start);
+        }
+
+        let span = (end as i64 - start as i64 + 1) as u64;
+
+        PacketDatum::Integer((start as i64 + self.rng.below(span) as i64) as i32)
+    }
+
+    // level: depth of the list this part of the template is in, 0 for the root
+    fn fill_template(&mut self, template: &Template, level: usize) -> PacketDatum {
+        match template {
+            Template::Integer(i) => PacketDatum::Integer(*i),
+            Template::String(s) => PacketDatum::String(s.clone()),
+            Template::RandomInteger => self.integer(),
+            Template::RandomItem => {
+                let depth = level + self.depth.sample(&mut self.rng);
+                self.item(level, depth)
+            }
+            Template::List(l) => PacketDatum::List(
+                l.iter()
+                    .map(|template| Rc::new(RefCell::new(self.fill_template(template, level + 1))))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// the packet grammar (Tokens in datum/text.rs), plus _ and * as items
+fn parse_template(s: &str) -> Result<Template, PacketParseError> {
+    let mut open_lists: Vec<Vec<Template>> = vec![];
+    let mut root = None;
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        if root.is_some() && !(c == ',' || c.is_ascii_whitespace()) {
+            return Err(PacketParseError);
+        }
+
+        let item = match c {
+            ',' => continue,
+            c if c.is_ascii_whitespace() => continue,
+            '[' => {
+                open_lists.push(vec![]);
+                continue;
+            }
+            ']' => Template::List(open_lists.pop().ok_or(PacketParseError)?),
+            '_' => Template::RandomInteger,
+            '*' => Template::RandomItem,
+            '-' | '0'..='9' => {
+                let mut end = idx + 1;
+
+                while let Some((next_idx, c)) = chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+
+                    end = next_idx + 1;
+                    chars.next();
+                }
+
+                Template::Integer(s[idx..end].parse().map_err(|_| PacketParseError)?)
+            }
+            '"' => {
+                let end = scan_string(s, idx).map_err(|_| PacketParseError)?;
+                while chars.next_if(|(next_idx, _)| *next_idx < end).is_some() {}
+
+                Template::String(unescape(&s[idx + 1..end - 1]))
+            }
+            _ => return Err(PacketParseError),
+        };
+
+        match open_lists.last_mut() {
+            Some(parent) => parent.push(item),
+            None => match item {
+                Template::List(_) => root = Some(item),
+                _ => return Err(PacketParseError),
+            },
+        }
+    }
+
+    match (root, open_lists.is_empty()) {
+        (Some(root), true) => Ok(root),
+        _ => Err(PacketParseError),
+    }
+}
+
+// xorshift64*, seeded through splitmix64 so nearby seeds (and 0) give unrelated streams
+#[derive(Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // 0..n, n > 0
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+// Seeded Day 13 style input (puzzle shaped packets) for the tests of other modules
+#[cfg(test)]
+pub(crate) fn test_input(seed: u64, pairs: usize) -> String {
+    let mut input = vec![];
+    PacketGenerator::new(seed)
+        .write_input(pairs, &mut input)
+        .expect("writing to a Vec");
+
+    String::from_utf8(input).expect("packets print as UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::read_packets;
+
+    fn depth(packet_datum: &PacketDatum) -> usize {
+        match packet_datum {
+            PacketDatum::Integer(_) | PacketDatum::String(_) => 0,
+            PacketDatum::List(l) => 1 + l.iter().map(|p| depth(&p.borrow())).max().unwrap_or(0),
+        }
+    }
+
+    // every list in the packet
+    fn lists(packet_datum: &PacketDatum) -> Vec<PacketDatum> {
+        match packet_datum {
+            PacketDatum::Integer(_) | PacketDatum::String(_) => vec![],
+            PacketDatum::List(l) => std::iter::once(packet_datum.clone())
+                .chain(l.iter().flat_map(|p| lists(&p.borrow())))
+                .collect(),
+        }
+    }
+
+    fn integers(packet_datum: &PacketDatum) -> Vec<i32> {
+        match packet_datum {
+            PacketDatum::Integer(i) => vec![*i],
+            PacketDatum::String(_) => vec![],
+            PacketDatum::List(l) => l.iter().flat_map(|p| integers(&p.borrow())).collect(),
+        }
+    }
+
+    fn strings(packet_datum: &PacketDatum) -> Vec<String> {
+        match packet_datum {
+            PacketDatum::String(s) => vec![s.clone()],
+            PacketDatum::Integer(_) => vec![],
+            PacketDatum::List(l) => l.iter().flat_map(|p| strings(&p.borrow())).collect(),
+        }
+    }
+
+    #[test]
+    fn same_seed_same_packets() {
+        let mut output_1 = vec![];
+        let mut output_2 = vec![];
+        let mut output_3 = vec![];
+
+        PacketGenerator::new(7)
+            .write_input(50, &mut output_1)
+            .unwrap();
+        PacketGenerator::new(7)
+            .write_input(50, &mut output_2)
+            .unwrap();
+        PacketGenerator::new(8)
+            .write_input(50, &mut output_3)
+            .unwrap();
+
+        assert!(output_1 == output_2);
+        assert!(output_1 != output_3);
+    }
+
+    #[test]
+    fn depth_distribution() {
+        let mut generator = PacketGenerator::new(1).depth(Distribution::Constant(5));
+
+        for _ in 0..100 {
+            assert!(depth(&generator.generate()) == 5);
+        }
+
+        let mut generator =
+            PacketGenerator::new(1).depth(Distribution::Weighted(vec![(1, 1), (3, 1), (7, 0)]));
+        let depths: Vec<usize> = (0..100).map(|_| depth(&generator.generate())).collect();
+
+        assert!(depths.iter().all(|d| *d == 1 || *d == 3));
+        assert!(depths.contains(&1) && depths.contains(&3));
+    }
+
+    #[test]
+    fn list_lengths_and_integers() {
+        let mut generator = PacketGenerator::new(2)
+            .list_length(Distribution::Uniform(2..=3))
+            .integers(-50..=-40);
+
+        for _ in 0..100 {
+            let packet_datum = generator.generate();
+
+            for list in lists(&packet_datum) {
+                let PacketDatum::List(l) = list else {
+                    unreachable!()
+                };
+                assert!((2..=3).contains(&l.len()));
+            }
+
+            assert!(integers(&packet_datum)
+                .iter()
+                .all(|i| (-50..=-40).contains(i)));
+        }
+
+        // the full i32 range doesn't overflow
+        let mut generator = PacketGenerator::new(3).integers(i32::MIN..=i32::MAX);
+        for _ in 0..100 {
+            generator.generate();
+        }
+    }
+
+    #[test]
+    fn full_uniform_range() {
+        let mut rng = Rng::new(4);
+        let values: Vec<usize> = (0..100)
+            .map(|_| Distribution::Uniform(0..=usize::MAX).sample(&mut rng))
+            .collect();
+
+        assert!(values.iter().any(|value| *value > u32::MAX as usize));
+
+        let mut rng = Rng::new(4);
+        for _ in 0..100 {
+            assert!(Distribution::Uniform(1..=usize::MAX).sample(&mut rng) >= 1);
+        }
+    }
+
+    #[test]
+    fn labels() {
+        let mut generator = PacketGenerator::new(9)
+            .labels(&["temp", "a\"b"])
+            .string_probability(0.5);
+
+        let packets: Vec<PacketDatum> = (0..100).map(|_| generator.generate()).collect();
+        let strings: Vec<String> = packets.iter().flat_map(strings).collect();
+
+        assert!(strings.iter().all(|s| s == "temp" || s == "a\"b"));
+        assert!(strings.contains(&"temp".to_string()) && strings.contains(&"a\"b".to_string()));
+        assert!(packets.iter().any(|p| !integers(p).is_empty()));
+
+        // string_probability 0 draws nothing extra: same packets as without labels
+        let mut with_labels = PacketGenerator::new(10).labels(&["temp"]);
+        let mut without = PacketGenerator::new(10);
+        for _ in 0..20 {
+            assert!(with_labels.generate() == without.generate());
+        }
+    }
+
+    #[test]
+    // every list not needed for the depth is empty
+    fn empty_lists() {
+        let mut generator = PacketGenerator::new(4)
+            .depth(Distribution::Constant(3))
+            .list_probability(1.0)
+            .empty_list_probability(1.0);
+
+        for _ in 0..20 {
+            let packet_datum = generator.generate();
+            assert!(depth(&packet_datum) == 3);
+
+            let empty = lists(&packet_datum)
+                .iter()
+                .filter(|list| **list == PacketDatum::List(vec![]))
+                .count();
+            // the root and the list at depth 2 lead down to depth 3
+            assert!(empty == lists(&packet_datum).len() - 2);
+        }
+    }
+
+    #[test]
+    fn templates() {
+        let mut generator = PacketGenerator::new(5)
+            .template("[[2],_,*]")
+            .unwrap()
+            .template("[[6],-1]")
+            .unwrap();
+
+        let packets: Vec<String> = (0..50).map(|_| generator.generate().to_string()).collect();
+
+        assert!(packets
+            .iter()
+            .all(|p| p.starts_with("[[2],") || p == "[[6],-1]"));
+        assert!(packets.iter().any(|p| p == "[[6],-1]"));
+
+        let mut generator = PacketGenerator::new(5).template(r#"["a,]",_]"#).unwrap();
+        assert!(strings(&generator.generate()) == vec!["a,]".to_string()]);
+
+        for invalid in [
+            "",
+            "_",
+            "[_",
+            "[1]]",
+            "[a]",
+            "[1][2]",
+            "[-]",
+            r#"["a]"#,
+            "[_\u{a0}_]",
+        ] {
+            assert!(PacketGenerator::new(5).template(invalid).is_err());
+        }
+    }
+
+    #[test]
+    // pairs separated by blank lines, every packet parses
+    fn day_thirteen_input() {
+        let mut output = vec![];
+        PacketGenerator::new(6)
+            .write_input(10, &mut output)
+            .unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.lines().count() == 30);
+        assert!(text.lines().skip(2).step_by(3).all(str::is_empty));
+
+        let packets = read_packets(text.as_bytes()).unwrap();
+        assert!(packets.len() == 20);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::datum::{parse_recovering, PacketDatum, PacketParseError, Recovered};
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Input Module:
+/// Reading packets from puzzle style input: one packet per line,
+/// blank lines (between pairs) are skipped.
+///
+/// Contains:
+/// - packet_lines: iterator over the non blank lines and their 1-based line numbers
+/// - read_packets: parses every line into a PacketDatum
+/// - check_packets: parses every line past its errors, to report every broken packet in one pass
+/// - InputError: an io error, or the line number of a packet that didn't parse
+pub fn packet_lines<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<(usize, String)>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(|(idx, line)| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(Ok((idx + 1, line))),
+            Err(e) => Some(Err(e)),
+        })
+}
+
+pub fn read_packets<R: BufRead>(reader: R) -> Result<Vec<PacketDatum>, InputError> {
+    parse_packets(reader).collect()
+}
+
+/// Lazily parses every packet line, so callers that only need one packet at a time
+/// never hold the whole file.
+pub fn parse_packets<R: BufRead>(
+    reader: R,
+) -> impl Iterator<Item = Result<PacketDatum, InputError>> {
+    packet_lines(reader).map(|line| {
+        let (line_number, line) = line?;
+
+        line.trim()
+            .parse()
+            .map_err(|_: PacketParseError| InputError::Parse { line: line_number })
+    })
+}
+
+/// Every packet line with its 1-based line number and what parse_recovering made of it.
+/// Broken packets don't stop the iterator, only io errors do.
+pub fn check_packets<R: BufRead>(
+    reader: R,
+) -> impl Iterator<Item = io::Result<(usize, Recovered)>> {
+    packet_lines(reader).map(|line| {
+        let (line_number, line) = line?;
+
+        Ok((line_number, parse_recovering(&line)))
+    })
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+    /// 1-based line number of the packet that failed to parse
+    Parse {
+        line: usize,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Io(e) => write!(f, "could not read packets: {}", e),
+            InputError::Parse { line } => write!(f, "invalid packet on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for InputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InputError::Io(e) => Some(e),
+            InputError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for InputError {
+    fn from(e: io::Error) -> Self {
+        InputError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_blank_lines() {
+        let input = "[1,1,3,1,1]\n[1,1,5,1,1]\n\n[[1],[2,3,4]]\n[[1],4]\n";
+
+        let packets = read_packets(input.as_bytes()).unwrap();
+
+        assert!(packets.len() == 4);
+        assert!(packets[3] == "[[1],4]".parse().unwrap());
+    }
+
+    #[test]
+    fn reports_line_number() {
+        let input = "[1,1,3,1,1]\n[1,1,5,1,1]\n\n[[1],[2,3,4]\n[[1],4]\n";
+
+        match read_packets(input.as_bytes()) {
+            Err(InputError::Parse { line }) => assert!(line == 4),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn checks_every_packet() {
+        let input = "[1,1,3,1,1]\n[1,1 x,1,1]\n\n[[1],[2,3,4]\n[[1],,4]]]\n";
+
+        let checked: Vec<(usize, Recovered)> = check_packets(input.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+
+        let errors: Vec<(usize, usize)> = checked
+            .iter()
+            .map(|(line, recovered)| (*line, recovered.errors.len()))
+            .collect();
+
+        assert!(errors == vec![(1, 0), (2, 1), (4, 1), (5, 2)]);
+        assert!(checked[2].1.packet == Some("[[1],[2,3,4]]".parse().unwrap()));
+    }
+}
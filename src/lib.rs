@@ -4,7 +4,44 @@
 /// Lexicographically Ordering and Comparing Nested Structures
 ///
 /// Crate contains:
+/// - Arbitrary Module (cargo feature "proptest")
+///     - proptest strategies for packets and a reusable property test suite
+/// - Arena Module
+///     - PacketArena: flat, index based storage for large numbers of packets
+/// - Codec Module (cargo feature "tokio")
+///     - PacketCodec: tokio-util Decoder & Encoder for sending packets over TCP
 /// - Datum Module
 ///     - PacketDatum enum
 ///         - nested structure that has lexicographic ordering
+///         - with optional string atoms, ex: [1,"temp"]
+/// - Dividers Module
+///     - part 2 divider positions by counting instead of sorting
+/// - External Module
+///     - ExternalSort: merge sort for packet files bigger than memory
+/// - Generate Module
+///     - PacketGenerator: seeded random packets with controllable shapes, for load tests
+/// - Input Module
+///     - reading puzzle style input, one packet per line
+/// - Minimize Module
+///     - delta debugging a pair of packets down to a minimal pair that still fails
+/// - Parallel Module (cargo feature "parallel")
+///     - sorting large packet files across threads
+/// - Sorted Module
+///     - SortedPackets: always sorted collection of packets with rank queries
+/// - Stream Module
+///     - StreamParser: push parser for packets arriving over a socket in arbitrary chunks
+#[cfg(any(test, feature = "proptest"))]
+pub mod arbitrary;
+pub mod arena;
+#[cfg(feature = "tokio")]
+pub mod codec;
 pub mod datum;
+pub mod dividers;
+pub mod external;
+pub mod generate;
+pub mod input;
+pub mod minimize;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod sorted;
+pub mod stream;
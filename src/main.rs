@@ -0,0 +1,261 @@
+use packet_13_22::datum::PacketDatum;
+use packet_13_22::generate::{Distribution, PacketGenerator};
+use packet_13_22::input::{check_packets, read_packets};
+use packet_13_22::minimize::minimize;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::ops::RangeInclusive;
+use std::process::{Command, ExitCode, Stdio};
+use std::str::FromStr;
+
+/// packet-13-22 command line: one subcommand per tool, see USAGE.
+const USAGE: &str = "usage:
+    packet-13-22 check [FILE]
+    packet-13-22 minimize [PAIR_FILE] -- COMMAND [ARGS...]
+    packet-13-22 generate [OPTIONS]
+
+check:
+    reports every error in every packet of FILE (or stdin), and exits with a non-zero status if there were any.
+
+minimize:
+    shrinks the first two packets of PAIR_FILE (or stdin) to a minimal pair that still fails.
+    COMMAND gets each candidate pair on stdin, one packet per line,
+    and exits with status 0 while the pair still fails.
+
+generate:
+    writes random Day 13 style input: pairs of packets separated by blank lines.
+    --pairs N               pairs to write (100)
+    --seed N                same seed and options, same packets (0)
+    --depth DIST            depth of each packet, the root list is 1 (1..=4)
+    --length DIST           length of each list (0..=5)
+    --integers MIN..=MAX    (0..=10)
+    --list-probability P    chance an item is a list rather than an atom (0.3)
+    --label S               string atoms are picked from the labels. repeatable
+    --string-probability P  chance an atom is a label rather than an integer (0)
+    --empty P               chance a list is empty (0)
+    --template T            packet shape, _ is a random integer, * a random item. repeatable
+    --output FILE           (stdout)
+
+    DIST is N, MIN..=MAX (uniform), or VALUE:WEIGHT,VALUE:WEIGHT...";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("check") => check_command(&args[1..]),
+        Some("minimize") => minimize_command(&args[1..]),
+        Some("generate") => generate_command(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn check_command(args: &[String]) -> Result<(), String> {
+    let reader: Box<dyn BufRead> = match args {
+        [] => Box::new(io::stdin().lock()),
+        [path] => Box::new(BufReader::new(
+            File::open(path).map_err(|e| format!("{}: {}", path, e))?,
+        )),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut errors = 0;
+    let mut broken_packets = 0;
+
+    for checked in check_packets(reader) {
+        let (line_number, recovered) = checked.map_err(|e| e.to_string())?;
+
+        for error in &recovered.errors {
+            eprintln!("{}", error.render(line_number, color));
+        }
+
+        errors += recovered.errors.len();
+        broken_packets += usize::from(!recovered.is_ok());
+    }
+
+    match errors {
+        0 => Ok(()),
+        _ => Err(format!("{} errors in {} packets", errors, broken_packets)),
+    }
+}
+
+fn minimize_command(args: &[String]) -> Result<(), String> {
+    let split = args
+        .iter()
+        .position(|arg| arg == "--")
+        .ok_or_else(|| USAGE.to_string())?;
+
+    let command = &args[split + 1..];
+
+    let pair_file = match (&args[..split], command.is_empty()) {
+        ([], false) => None,
+        ([pair_file], false) => Some(pair_file),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let reader: Box<dyn BufRead> = match pair_file {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).map_err(|e| format!("{}: {}", path, e))?,
+        )),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let packets = read_packets(reader).map_err(|e| e.to_string())?;
+
+    let [left, right, ..] = packets.as_slice() else {
+        return Err("expected two packets to minimize".to_string());
+    };
+
+    let mut command_error = None;
+
+    let minimized = minimize(left, right, |left, right| {
+        match still_fails(command, left, right) {
+            Ok(fails) => fails,
+            Err(e) => {
+                command_error.get_or_insert(e);
+                false
+            }
+        }
+    });
+
+    if let Some(e) = command_error {
+        return Err(format!("{}: {}", command[0], e));
+    }
+
+    let minimized =
+        minimized.ok_or("the pair given doesn't fail: COMMAND exited with a non-zero status")?;
+
+    eprintln!(
+        "minimized from {} to {} characters in {} tests",
+        left.to_string().len() + right.to_string().len(),
+        minimized.left.to_string().len() + minimized.right.to_string().len(),
+        minimized.tests_run
+    );
+    println!("{}\n{}", minimized.left, minimized.right);
+
+    Ok(())
+}
+
+// runs the command with the pair on stdin: exit status 0 means the pair still fails
+fn still_fails(command: &[String], left: &PacketDatum, right: &PacketDatum) -> io::Result<bool> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+
+    // the command may exit without reading all of it
+    match writeln!(stdin, "{}\n{}", left, right) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => (),
+    }
+    drop(stdin);
+
+    Ok(child.wait()?.success())
+}
+
+fn generate_command(args: &[String]) -> Result<(), String> {
+    let mut pairs = 100;
+    let mut seed = 0;
+    let mut output = None;
+    let mut settings = vec![];
+
+    for option in args.chunks(2) {
+        let [name, value] = option else {
+            return Err(format!("{} needs a value\n\n{}", option[0], USAGE));
+        };
+
+        match name.as_str() {
+            "--pairs" => pairs = parse_value(name, value)?,
+            "--seed" => seed = parse_value(name, value)?,
+            "--output" => output = Some(value),
+            _ => settings.push((name, value)),
+        }
+    }
+
+    // the seed has to be known before the generator is made, so settings are applied after
+    let mut generator = PacketGenerator::new(seed);
+    let mut labels = vec![];
+
+    for (name, value) in settings {
+        generator = match name.as_str() {
+            "--depth" => generator.depth(parse_distribution(name, value)?),
+            "--length" => generator.list_length(parse_distribution(name, value)?),
+            "--integers" => generator.integers(parse_range(name, value)?),
+            "--list-probability" => generator.list_probability(parse_value(name, value)?),
+            "--empty" => generator.empty_list_probability(parse_value(name, value)?),
+            "--string-probability" => generator.string_probability(parse_value(name, value)?),
+            "--label" => {
+                labels.push(value.as_str());
+                generator
+            }
+            "--template" => generator
+                .template(value)
+                .map_err(|_| format!("--template: {} isn't a valid template", value))?,
+            _ => return Err(format!("unknown option {}\n\n{}", name, USAGE)),
+        };
+    }
+
+    if !labels.is_empty() {
+        generator = generator.labels(&labels);
+    }
+
+    let result = match output {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            generator.write_input(pairs, BufWriter::new(file))
+        }
+        None => generator.write_input(pairs, BufWriter::new(io::stdout().lock())),
+    };
+
+    result.map_err(|e| e.to_string())
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: can't read {:?}", name, value))
+}
+
+// MIN..=MAX
+fn parse_range<T: FromStr>(name: &str, value: &str) -> Result<RangeInclusive<T>, String> {
+    let (start, end) = value
+        .split_once("..=")
+        .ok_or_else(|| format!("{}: expected MIN..=MAX, got {:?}", name, value))?;
+
+    Ok(parse_value(name, start)?..=parse_value(name, end)?)
+}
+
+// N, MIN..=MAX or VALUE:WEIGHT,VALUE:WEIGHT...
+fn parse_distribution(name: &str, value: &str) -> Result<Distribution, String> {
+    if value.contains(':') {
+        let weights = value
+            .split(',')
+            .map(|pair| {
+                let (value, weight) = pair
+                    .split_once(':')
+                    .ok_or_else(|| format!("{}: expected VALUE:WEIGHT, got {:?}", name, pair))?;
+
+                Ok((parse_value(name, value)?, parse_value(name, weight)?))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Distribution::Weighted(weights))
+    } else if value.contains("..=") {
+        Ok(Distribution::Uniform(parse_range(name, value)?))
+    } else {
+        Ok(Distribution::Constant(parse_value(name, value)?))
+    }
+}
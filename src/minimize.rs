@@ -0,0 +1,454 @@
+use crate::datum::{PacketComparator, PacketDatum};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Delta debugging on a pair of packets.
+//
+// still_fails(left, right) is true while the pair still shows the problem.
+// Every candidate is a copy of one packet with one thing made smaller,
+// and it's kept only if the pair still fails. Passes, in order:
+//  1. remove items: for every list, try removing chunks of half its items, then a quarter ...
+//     down to single items (ddmin: big chunks first, so long lists shrink in few tests)
+//  2. unwrap singleton lists: [x] becomes x (the root stays a list, so [[1,2]] becomes [1,2])
+//  3. shrink integers toward 0: try 0, then halfway, then a quarter of the way ... then one step
+//  4. shorten strings: remove chunks of their chars, halves down to single chars like pass 1
+//
+// The passes repeat over both packets until a whole round changes nothing,
+// so the result is 1-minimal: no single removal, unwrap or shrink still fails.
+//
+// Working trees are plain Vecs, candidates are built as PacketDatums only to be tested.
+
+/// Minimize Module:
+/// Shrinks a pair of packets that shows a problem down to a small pair that still shows it.
+///
+/// ex: two comparators that disagree on a pair
+/// let minimized = minimize(&left, &right, comparators_disagree(DayThirteen, MyComparator))?;
+///
+/// Contains:
+/// - minimize: delta debugging over any predicate on PacketDatum pairs
+/// - comparators_disagree: predicate for two PacketComparators ordering a pair differently
+/// - Minimized: the smaller pair, and how many times the predicate ran
+#[derive(Debug)]
+pub struct Minimized {
+    pub left: PacketDatum,
+    pub right: PacketDatum,
+    /// how many candidate pairs were tested
+    pub tests_run: usize,
+}
+
+/// Shrinks (left, right) while still_fails stays true.
+///
+/// None if the pair given doesn't fail to begin with.
+pub fn minimize<F>(left: &PacketDatum, right: &PacketDatum, still_fails: F) -> Option<Minimized>
+where
+    F: FnMut(&PacketDatum, &PacketDatum) -> bool,
+{
+    let mut pair = Pair {
+        packets: [Node::from(left), Node::from(right)],
+        still_fails,
+        tests_run: 0,
+    };
+
+    if !pair.test(Side::Left, pair.packets[0].clone()) {
+        return None;
+    }
+
+    loop {
+        let mut changed = false;
+
+        for side in [Side::Left, Side::Right] {
+            changed |= pair.remove_items(side);
+            changed |= pair.unwrap_singletons(side);
+            changed |= pair.shrink_integers(side);
+            changed |= pair.shorten_strings(side);
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let [left, right] = pair.packets;
+
+    Some(Minimized {
+        left: left.to_datum(),
+        right: right.to_datum(),
+        tests_run: pair.tests_run,
+    })
+}
+
+/// Predicate for minimize: the two comparators order the pair differently.
+pub fn comparators_disagree<A, B>(a: A, b: B) -> impl FnMut(&PacketDatum, &PacketDatum) -> bool
+where
+    A: PacketComparator,
+    B: PacketComparator,
+{
+    move |left, right| a.compare(left, right) != b.compare(left, right)
+}
+
+#[derive(Clone, PartialEq)]
+enum Node {
+    Integer(i32),
+    String(String),
+    List(Vec<Node>),
+}
+
+impl From<&PacketDatum> for Node {
+    fn from(packet_datum: &PacketDatum) -> Self {
+        match packet_datum {
+            PacketDatum::Integer(i) => Node::Integer(*i),
+            PacketDatum::String(s) => Node::String(s.clone()),
+            PacketDatum::List(l) => {
+                Node::List(l.iter().map(|p| Node::from(&*p.borrow())).collect())
+            }
+        }
+    }
+}
+
+impl Node {
+    fn to_datum(&self) -> PacketDatum {
+        match self {
+            Node::Integer(i) => PacketDatum::Integer(*i),
+            Node::String(s) => PacketDatum::String(s.clone()),
+            Node::List(l) => PacketDatum::List(
+                l.iter()
+                    .map(|node| Rc::new(RefCell::new(node.to_datum())))
+                    .collect(),
+            ),
+        }
+    }
+
+    // paths (child indices from the root) of every node, parents before their children
+    fn paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        self.collect_paths(&mut vec![], &mut paths);
+
+        paths
+    }
+
+    fn collect_paths(&self, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        paths.push(path.clone());
+
+        if let Node::List(l) = self {
+            for (idx, child) in l.iter().enumerate() {
+                path.push(idx);
+                child.collect_paths(path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    fn get(&self, path: &[usize]) -> &Node {
+        path.iter().fold(self, |node, idx| match node {
+            Node::List(l) => &l[*idx],
+            Node::Integer(_) | Node::String(_) => unreachable!("paths only go through lists"),
+        })
+    }
+
+    // a copy of self with the node at path replaced
+    fn replaced(&self, path: &[usize], replacement: Node) -> Node {
+        let mut copy = self.clone();
+
+        let mut node = &mut copy;
+        for idx in path {
+            node = match node {
+                Node::List(l) => &mut l[*idx],
+                Node::Integer(_) | Node::String(_) => {
+                    unreachable!("paths only go through lists")
+                }
+            };
+        }
+        *node = replacement;
+
+        copy
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Left,
+    Right,
+}
+
+struct Pair<F> {
+    packets: [Node; 2],
+    still_fails: F,
+    tests_run: usize,
+}
+
+impl<F> Pair<F>
+where
+    F: FnMut(&PacketDatum, &PacketDatum) -> bool,
+{
+    // keeps candidate in place of side's packet if the pair still fails
+    fn test(&mut self, side: Side, candidate: Node) -> bool {
+        let mut packets = self.packets.clone();
+        packets[side as usize] = candidate;
+
+        self.tests_run += 1;
+
+        if (self.still_fails)(&packets[0].to_datum(), &packets[1].to_datum()) {
+            self.packets = packets;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn packet(&self, side: Side) -> &Node {
+        &self.packets[side as usize]
+    }
+
+    fn remove_items(&mut self, side: Side) -> bool {
+        let mut changed = false;
+        let mut node_idx = 0;
+
+        // removing items only removes nodes after node_idx, so the paths before it stay put
+        loop {
+            let paths = self.packet(side).paths();
+
+            let Some(path) = paths.get(node_idx) else {
+                break;
+            };
+
+            if let Node::List(l) = self.packet(side).get(path) {
+                let mut chunk = l.len().div_ceil(2);
+
+                while chunk > 0 {
+                    let mut start = 0;
+
+                    loop {
+                        let Node::List(l) = self.packet(side).get(path) else {
+                            unreachable!("removing items doesn't change what this node is")
+                        };
+
+                        if start >= l.len() {
+                            break;
+                        }
+
+                        let mut smaller = l.clone();
+                        smaller.drain(start..(start + chunk).min(l.len()));
+
+                        let candidate = self.packet(side).replaced(path, Node::List(smaller));
+
+                        if self.test(side, candidate) {
+                            changed = true;
+                        } else {
+                            start += chunk;
+                        }
+                    }
+
+                    chunk /= 2;
+                }
+            }
+
+            node_idx += 1;
+        }
+
+        changed
+    }
+
+    fn unwrap_singletons(&mut self, side: Side) -> bool {
+        let mut changed = false;
+        let mut node_idx = 0;
+
+        // unwrapping a node only changes the nodes at and after node_idx
+        loop {
+            let paths = self.packet(side).paths();
+
+            let Some(path) = paths.get(node_idx) else {
+                break;
+            };
+
+            if let Node::List(l) = self.packet(side).get(path) {
+                let unwrappable = match l.as_slice() {
+                    // the root has to stay a list
+                    [Node::List(_)] => true,
+                    [Node::Integer(_) | Node::String(_)] => !path.is_empty(),
+                    _ => false,
+                };
+
+                if unwrappable {
+                    let candidate = self.packet(side).replaced(path, l[0].clone());
+
+                    if self.test(side, candidate) {
+                        changed = true;
+                        // the child is now at node_idx, try unwrapping it too
+                        continue;
+                    }
+                }
+            }
+
+            node_idx += 1;
+        }
+
+        changed
+    }
+
+    fn shrink_integers(&mut self, side: Side) -> bool {
+        let mut changed = false;
+
+        // shrinking integers doesn't move any nodes
+        for path in self.packet(side).paths() {
+            while let Node::Integer(i) = *self.packet(side).get(&path) {
+                // 0 first, then closer and closer to i: i - i/2, i - i/4 ... i - 1
+                let mut candidates = vec![];
+                let mut step = i;
+
+                while step != 0 {
+                    candidates.push(i - step);
+                    step /= 2;
+                }
+
+                let smaller = candidates.into_iter().find(|smaller| {
+                    let candidate = self.packet(side).replaced(&path, Node::Integer(*smaller));
+                    self.test(side, candidate)
+                });
+
+                match smaller {
+                    Some(_) => changed = true,
+                    None => break,
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn shorten_strings(&mut self, side: Side) -> bool {
+        let mut changed = false;
+
+        // shortening strings doesn't move any nodes
+        for path in self.packet(side).paths() {
+            let Node::String(s) = self.packet(side).get(&path) else {
+                continue;
+            };
+
+            let mut chunk = s.chars().count().div_ceil(2);
+
+            while chunk > 0 {
+                let mut start = 0;
+
+                loop {
+                    let Node::String(s) = self.packet(side).get(&path) else {
+                        unreachable!("shortening a string doesn't change what this node is")
+                    };
+
+                    let mut chars: Vec<char> = s.chars().collect();
+
+                    if start >= chars.len() {
+                        break;
+                    }
+
+                    chars.drain(start..(start + chunk).min(chars.len()));
+
+                    let shorter = Node::String(chars.into_iter().collect());
+                    let candidate = self.packet(side).replaced(&path, shorter);
+
+                    if self.test(side, candidate) {
+                        changed = true;
+                    } else {
+                        start += chunk;
+                    }
+                }
+
+                chunk /= 2;
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::parse;
+    use crate::datum::{DayThirteen, NoPromotion};
+    use std::cmp::Ordering;
+
+    #[test]
+    // they disagree whenever an integer meets a list, the smallest case is 0 vs []
+    fn minimizes_comparator_disagreement() {
+        let left = parse("[[1,2,[4,5,6]],[7,[8,[9]]],3,[10,10,10],[[[0]]]]");
+        let right = parse("[[1,2,[4,5,6]],[7,[8,[9]]],[3],[2],[]]");
+
+        let minimized = minimize(
+            &left,
+            &right,
+            comparators_disagree(DayThirteen, NoPromotion),
+        )
+        .unwrap();
+
+        assert!(minimized.left.to_string() == "[0]");
+        assert!(minimized.right.to_string() == "[[]]");
+        assert!(DayThirteen.compare(&minimized.left, &minimized.right) == Ordering::Greater);
+        assert!(NoPromotion.compare(&minimized.left, &minimized.right) == Ordering::Less);
+        assert!(minimized.tests_run > 0);
+    }
+
+    #[test]
+    // 1000 shrinks to just over the threshold
+    fn shrinks_integers() {
+        let left = parse("[1000,[-2000,50]]");
+        let right = parse("[]");
+        let threshold = parse("[99]");
+
+        let minimized = minimize(&left, &right, |left, _| *left > threshold).unwrap();
+
+        assert!(minimized.left.to_string() == "[100]");
+        assert!(minimized.right.to_string() == "[]");
+    }
+
+    #[test]
+    // [[[[5]]]] unwraps all the way down to [5]
+    fn unwraps_singletons() {
+        let left = parse("[[[[5]]],[]]");
+        let right = parse("[4]");
+
+        let minimized = minimize(&left, &right, |left, right| {
+            left.to_string().contains('5') && left > right
+        })
+        .unwrap();
+
+        assert!(minimized.left.to_string() == "[5]");
+        assert!(minimized.right.to_string() == "[]");
+    }
+
+    #[test]
+    // only the chars that matter are left, and ["b"] unwraps like an integer would
+    fn shortens_strings() {
+        let left = parse(r#"[["a long label with a b in it"],1]"#);
+        let right = parse(r#"["a"]"#);
+
+        let minimized = minimize(&left, &right, |left, right| {
+            left.to_string().contains('b') && left > right
+        })
+        .unwrap();
+
+        assert!(minimized.left.to_string() == r#"["b"]"#);
+        assert!(minimized.right.to_string() == "[]");
+    }
+
+    #[test]
+    fn pair_that_does_not_fail() {
+        let (left, right) = (parse("[1]"), parse("[2]"));
+
+        assert!(minimize(&left, &right, |left, right| left > right).is_none());
+    }
+
+    #[test]
+    // a long packet with one bad item in the middle
+    fn long_packets() {
+        let items: Vec<String> = (0..2000).map(|i| (i % 10).to_string()).collect();
+        let left = parse(&format!("[{},[9],{}]", items.join(","), items.join(",")));
+        let right = parse("[]");
+
+        let minimized = minimize(&left, &right, |left, _| left.to_string().contains("[9")).unwrap();
+
+        // [[9]] unwrapped
+        assert!(minimized.left.to_string() == "[9]");
+        // ddmin removes the 4000 integers in far fewer tests than one at a time
+        assert!(minimized.tests_run < 500);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::datum::{compare_validated, validate_str, PacketDatum, SyncPacketDatum};
+use crate::input::InputError;
+use rayon::prelude::*;
+use std::io;
+
+// PacketDatum is built out of Rc<RefCell<..>> so it can't be sent between threads.
+// SyncPacketDatum (Arc based) can, and so can the packet text itself.
+//
+// sort_lines keeps the packets as text:
+// 1. every line is validated concurrently (in chunks of lines)
+// 2. the lines are sorted with rayon's parallel stable merge sort, comparing the text directly
+//
+// sort_sync_packets parses instead:
+// 1. every line is parsed into a SyncPacketDatum concurrently (in chunks of lines)
+// 2. the packets are sorted with the parallel stable merge sort
+//
+// sort_packets is sort_sync_packets followed by converting to PacketDatums. That last step
+// can only happen on the calling thread, since the PacketDatums have to end up there.
+//
+// All three accept the same lines: SyncPacketDatum's parser reads the same tokens as
+// validate_str, so a line with junk ("[1,a]") or trailing text ("[1][2]") is an error in each.
+//
+// Because the sorts are stable and every ordering agrees with cmp,
+// the result is exactly what sorting the parsed packets sequentially gives.
+
+/// Parallel Module (cargo feature "parallel"):
+/// Sorting large packet files across threads.
+///
+/// Contains:
+/// - sort_lines: packet lines sorted by Day 13 rules, still as text
+/// - sort_sync_packets: the same, parsed across threads into SyncPacketDatums
+/// - sort_packets: the same, as PacketDatums
+pub fn sort_lines(input: &str, threads: usize) -> Result<Vec<&str>, InputError> {
+    in_pool(threads, || {
+        let mut lines = numbered_lines(input);
+
+        let invalid = lines
+            .par_iter()
+            .with_min_len(CHUNK_LINES)
+            .find_first(|(_, line)| validate_str(line).is_err());
+
+        if let Some((line_number, _)) = invalid {
+            return Err(InputError::Parse { line: *line_number });
+        }
+
+        lines.par_sort_by(|(_, line_1), (_, line_2)| compare_validated(line_1, line_2));
+
+        Ok(lines.into_iter().map(|(_, line)| line).collect())
+    })
+}
+
+/// Parses every line into a SyncPacketDatum across threads, then sorts them across threads.
+///
+/// A line that doesn't parse (the same lines validate_str rejects) is an InputError::Parse
+/// with the first such line number.
+pub fn sort_sync_packets(input: &str, threads: usize) -> Result<Vec<SyncPacketDatum>, InputError> {
+    in_pool(threads, || {
+        let parsed: Vec<Result<SyncPacketDatum, InputError>> = numbered_lines(input)
+            .into_par_iter()
+            .with_min_len(CHUNK_LINES)
+            .map(|(line_number, line)| {
+                line.parse()
+                    .map_err(|_| InputError::Parse { line: line_number })
+            })
+            .collect();
+
+        // in line order, so the error is the first invalid line
+        let mut packets = parsed.into_iter().collect::<Result<Vec<_>, _>>()?;
+        packets.par_sort();
+
+        Ok(packets)
+    })
+}
+
+/// Same as sort_sync_packets, converted into PacketDatums on the calling thread.
+///
+/// Returns the same packets in the same order as parsing every line and calling sort.
+pub fn sort_packets(input: &str, threads: usize) -> Result<Vec<PacketDatum>, InputError> {
+    Ok(sort_sync_packets(input, threads)?
+        .iter()
+        .map(PacketDatum::from)
+        .collect())
+}
+
+fn in_pool<T>(
+    threads: usize,
+    f: impl FnOnce() -> Result<T, InputError> + Send,
+) -> Result<T, InputError>
+where
+    T: Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| InputError::Io(io::Error::other(e)))?;
+
+    pool.install(f)
+}
+
+// (1-based line number, line) pairs, blank lines skipped
+fn numbered_lines(input: &str) -> Vec<(usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect()
+}
+
+// lines per rayon task, small enough to balance and big enough to not be all overhead
+const CHUNK_LINES: usize = 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::test_input;
+    use crate::input;
+
+    #[test]
+    fn matches_sequential_sort() {
+        let input = test_input(1, 2_500);
+
+        let mut expected = input::read_packets(input.as_bytes()).unwrap();
+        expected.sort();
+
+        let expected_lines: Vec<String> = expected.iter().map(|p| p.to_string()).collect();
+
+        for threads in [1, 4, 16] {
+            let sorted = sort_packets(&input, threads).unwrap();
+            assert!(sorted == expected);
+
+            let sorted_lines: Vec<String> = sort_lines(&input, threads)
+                .unwrap()
+                .iter()
+                .map(|line| line.parse::<PacketDatum>().unwrap().to_string())
+                .collect();
+            assert!(sorted_lines == expected_lines);
+        }
+    }
+
+    #[test]
+    fn reports_first_invalid_line() {
+        let input = "[1,1,3,1,1]\n[1,1,5,1,1]\n\n[[1],[2,3,4]\n[[1],4\n";
+
+        match sort_lines(input, 4) {
+            Err(InputError::Parse { line }) => assert!(line == 4),
+            _ => panic!("expected a parse error"),
+        }
+
+        match sort_sync_packets(input, 4) {
+            Err(InputError::Parse { line }) => assert!(line == 4),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    // the same bad line is an error for every entry point, whichever way it's malformed
+    fn rejects_the_same_lines() {
+        for bad_line in ["[1,a]", "[1][2]", "[1]]", "[1", "x"] {
+            let input = format!("[1,1,3,1,1]\n[1,1,5,1,1]\n\n{}\n[[1],4]\n", bad_line);
+            let is_line_4 = |result: Result<usize, InputError>| {
+                matches!(result, Err(InputError::Parse { line: 4 }))
+            };
+
+            assert!(is_line_4(sort_lines(&input, 4).map(|lines| lines.len())));
+            assert!(is_line_4(sort_sync_packets(&input, 4).map(|p| p.len())));
+            assert!(is_line_4(sort_packets(&input, 4).map(|p| p.len())));
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::datum::{DayThirteen, PacketComparator, PacketDatum};
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+/// SortedPackets:
+/// A collection of packets that always stays sorted, by Day 13 rules unless another
+/// PacketComparator is given.
+///
+/// Part 2 of the puzzle is: put the divider packets in with the rest, sort,
+/// then find where the dividers ended up. With SortedPackets that's an insert and a rank query.
+///
+/// Backed by a sorted Vec:
+/// - rank, contains, range: O(log N) binary searches
+/// - nth: O(1)
+/// - insert, remove: O(log N) search + shifting the packets after it
+/// - bulk loading: one O(N log N) sort
+pub struct SortedPackets<C: PacketComparator = DayThirteen> {
+    packets: Vec<PacketDatum>,
+    comparator: C,
+}
+
+impl SortedPackets<DayThirteen> {
+    pub fn new() -> Self {
+        SortedPackets::with_comparator(DayThirteen)
+    }
+}
+
+impl Default for SortedPackets<DayThirteen> {
+    fn default() -> Self {
+        SortedPackets::new()
+    }
+}
+
+impl<C: PacketComparator> SortedPackets<C> {
+    pub fn with_comparator(comparator: C) -> Self {
+        SortedPackets {
+            packets: vec![],
+            comparator,
+        }
+    }
+
+    /// Bulk load: sorts once instead of inserting packets one at a time.
+    pub fn from_packets<I>(packets: I, comparator: C) -> Self
+    where
+        I: IntoIterator<Item = PacketDatum>,
+    {
+        let mut packets: Vec<PacketDatum> = packets.into_iter().collect();
+        comparator.sort(&mut packets);
+
+        SortedPackets {
+            packets,
+            comparator,
+        }
+    }
+
+    /// Adds more packets, sorting once for the whole batch.
+    pub fn extend<I>(&mut self, packets: I)
+    where
+        I: IntoIterator<Item = PacketDatum>,
+    {
+        self.packets.extend(packets);
+        self.comparator.sort(&mut self.packets);
+    }
+
+    /// Inserts after any packets that compare Equal, and returns the index it was inserted at.
+    pub fn insert(&mut self, packet: PacketDatum) -> usize {
+        let idx = self.upper_bound(&packet);
+        self.packets.insert(idx, packet);
+
+        idx
+    }
+
+    /// Removes one packet that compares Equal to the probe, if there is one.
+    pub fn remove(&mut self, probe: &PacketDatum) -> Option<PacketDatum> {
+        let idx = self.rank(probe);
+
+        match self.packets.get(idx) {
+            Some(packet) if self.comparator.compare(packet, probe) == Ordering::Equal => {
+                Some(self.packets.remove(idx))
+            }
+            _ => None,
+        }
+    }
+
+    /// Number of packets strictly less than the probe.
+    ///
+    /// That's also the 0-based index the probe is (or would be) sorted into,
+    /// so a divider's 1-based position is rank + 1.
+    pub fn rank(&self, probe: &PacketDatum) -> usize {
+        self.packets
+            .partition_point(|packet| self.comparator.compare(packet, probe) == Ordering::Less)
+    }
+
+    pub fn contains(&self, probe: &PacketDatum) -> bool {
+        match self.packets.get(self.rank(probe)) {
+            Some(packet) => self.comparator.compare(packet, probe) == Ordering::Equal,
+            None => false,
+        }
+    }
+
+    /// The packet at 0-based position n in sorted order.
+    pub fn nth(&self, n: usize) -> Option<&PacketDatum> {
+        self.packets.get(n)
+    }
+
+    /// Every packet inside the range, ex: sorted.range(&low..&high).
+    pub fn range<'a, R>(&self, range: R) -> &[PacketDatum]
+    where
+        R: RangeBounds<&'a PacketDatum>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(low) => self.rank(low),
+            Bound::Excluded(low) => self.upper_bound(low),
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(high) => self.upper_bound(high),
+            Bound::Excluded(high) => self.rank(high),
+            Bound::Unbounded => self.packets.len(),
+        };
+
+        if start >= end {
+            return &[];
+        }
+
+        &self.packets[start..end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PacketDatum> {
+        self.packets.iter()
+    }
+
+    pub fn as_slice(&self) -> &[PacketDatum] {
+        &self.packets
+    }
+
+    pub fn into_vec(self) -> Vec<PacketDatum> {
+        self.packets
+    }
+
+    // number of packets less than or equal to the probe
+    fn upper_bound(&self, probe: &PacketDatum) -> usize {
+        self.packets
+            .partition_point(|packet| self.comparator.compare(packet, probe) != Ordering::Greater)
+    }
+}
+
+impl FromIterator<PacketDatum> for SortedPackets<DayThirteen> {
+    fn from_iter<I: IntoIterator<Item = PacketDatum>>(packets: I) -> Self {
+        SortedPackets::from_packets(packets, DayThirteen)
+    }
+}
+
+impl<'a, C: PacketComparator> IntoIterator for &'a SortedPackets<C> {
+    type Item = &'a PacketDatum;
+    type IntoIter = std::slice::Iter<'a, PacketDatum>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SortedPackets;
+    use crate::datum::tests::{parse, EXAMPLE_PACKETS};
+    use crate::datum::{LongerListFirst, PacketDatum as pd};
+
+    // the example's pairs, without the divider packets
+    fn example_packets() -> impl Iterator<Item = pd> {
+        EXAMPLE_PACKETS[..16].iter().map(|s| parse(s))
+    }
+
+    #[test]
+    // Part 2: insert [[2]] and [[6]], the decoder key is 10 * 14 = 140
+    fn divider_packets() {
+        let mut sorted: SortedPackets = example_packets().collect();
+
+        let divider_1 = parse("[[2]]");
+        let divider_2 = parse("[[6]]");
+        sorted.insert(divider_1.clone());
+        sorted.insert(divider_2.clone());
+
+        let decoder_key = (sorted.rank(&divider_1) + 1) * (sorted.rank(&divider_2) + 1);
+        assert!(decoder_key == 140);
+
+        // the dividers don't need to be inserted: [[6]] just has [[2]] ahead of it too
+        sorted.remove(&divider_1);
+        sorted.remove(&divider_2);
+        assert!(sorted.len() == 16);
+        assert!((sorted.rank(&divider_1) + 1) * (sorted.rank(&divider_2) + 2) == 140);
+    }
+
+    #[test]
+    fn insert_keeps_sorted() {
+        let mut sorted = SortedPackets::new();
+
+        for packet in example_packets() {
+            sorted.insert(packet);
+        }
+
+        let bulk: SortedPackets = example_packets().collect();
+        assert!(sorted.as_slice() == bulk.as_slice());
+        assert!(sorted.nth(0) == Some(&parse("[]")));
+        assert!(sorted.nth(15) == Some(&parse("[9]")));
+        assert!(sorted.nth(16).is_none());
+    }
+
+    #[test]
+    // [3] and [[3]] compare Equal, the later insert goes after the earlier one
+    fn insert_after_equal() {
+        let mut sorted = SortedPackets::new();
+
+        assert!(sorted.insert(parse("[3]")) == 0);
+        assert!(sorted.insert(parse("[[3]]")) == 1);
+        assert!(sorted.insert(parse("[2]")) == 0);
+        assert!(sorted.rank(&parse("[[[3]]]")) == 1);
+    }
+
+    #[test]
+    fn remove_and_contains() {
+        let mut sorted: SortedPackets = example_packets().collect();
+
+        assert!(sorted.contains(&parse("[[1],4]")));
+        assert!(sorted.remove(&parse("[[1],4]")).is_some());
+        assert!(!sorted.contains(&parse("[[1],4]")));
+        assert!(sorted.remove(&parse("[[1],4]")).is_none());
+        assert!(sorted.len() == 15);
+    }
+
+    #[test]
+    fn range() {
+        let sorted: SortedPackets = example_packets().collect();
+        let (low, high) = (parse("[1,1,5,1,1]"), parse("[[4,4],4,4,4]"));
+
+        let exclusive: Vec<&pd> = sorted.range(&low..&high).iter().collect();
+        assert!(exclusive.len() == 7);
+        assert!(*exclusive[0] == low);
+
+        let inclusive = sorted.range(&low..=&high);
+        assert!(inclusive.len() == 8);
+        assert!(inclusive[7] == high);
+
+        assert!(sorted.range(..&low).len() == 4);
+        assert!(sorted.range(&high..).len() == 5);
+        assert!(sorted.range(&high..&low).is_empty());
+    }
+
+    #[test]
+    fn other_comparators() {
+        let sorted = SortedPackets::from_packets(example_packets(), LongerListFirst);
+
+        // [] runs out of items before everything else, so it's now the largest
+        assert!(sorted.nth(15) == Some(&parse("[]")));
+        assert!(sorted.rank(&parse("[]")) == 15);
+        assert!(sorted.rank(&parse("[7,7,7,7]")) < sorted.rank(&parse("[7,7,7]")));
+    }
+}
@@ -0,0 +1,461 @@
+use crate::datum::{scan_string, unescape, PacketDatum, StringError};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+// A push parser: the bytes come in whatever pieces the socket hands out,
+// so every bit of state that a token can be split across lives in the parser between feeds.
+//
+// Same grammar as validate_str: brackets, i32s and quoted strings, commas and whitespace
+// separate tokens.
+// Packets follow each other in the stream, separated by any number of commas and whitespace.
+//
+// State between feeds:
+// - open_lists: one Vec of children per open [, the root at the bottom. A ] pops the top one
+//   into its parent, or into ready when it's the root
+// - integer: an integer whose digits may continue in the next chunk. Digits are accumulated
+//   as a negative number (so i32::MIN fits) and it only ends when a byte that isn't a digit comes
+// - string: the bytes of a string since its opening quote, and whether the last one was a \
+//   (then the next " doesn't close it). Once it closes it's checked and unescaped in one go,
+//   so a char or an escape split between chunks is no different from any other
+// - packet_size: bytes since the root [, checked against max_packet_size on every byte
+//
+// That's bounded by the limits: at most max_depth open lists holding at most max_packet_size bytes
+// worth of items, plus the packets finished in the last chunk that haven't been taken yet.
+//
+// A stream that breaks the grammar or a limit can't be resynchronized (there's no telling where
+// the next packet starts), so the first error sticks: every feed after it returns it again.
+
+/// Stream Module:
+/// Parsing packets out of a byte stream that arrives in arbitrary chunks.
+///
+/// ex:
+/// let mut parser = StreamParser::new().max_depth(64);
+/// parser.feed(b"[1,[2")?;
+/// parser.feed(b"3]]\n[")?;
+/// parser.next_packet() == Some([1,[23]])
+///
+/// Contains:
+/// - StreamParser: feed it bytes, take complete packets out as soon as their root list closes
+/// - StreamError: the offset in the stream where it stopped being packets, or a limit that was hit
+pub struct StreamParser {
+    max_packet_size: usize,
+    max_depth: usize,
+    open_lists: Vec<Vec<Rc<RefCell<PacketDatum>>>>,
+    integer: Option<PartialInteger>,
+    string: Option<PartialString>,
+    packet_size: usize,
+    // bytes fed so far
+    offset: u64,
+    ready: VecDeque<PacketDatum>,
+    error: Option<StreamError>,
+}
+
+#[derive(Clone, Copy)]
+struct PartialInteger {
+    negative: bool,
+    // minus the digits read so far
+    value: i32,
+    digits: usize,
+}
+
+struct PartialString {
+    // offset of the opening "
+    start: u64,
+    // from the opening " on
+    bytes: Vec<u8>,
+    escaped: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamError {
+    /// byte offset in the stream of the first byte that can't be part of a packet
+    Parse { offset: u64 },
+    /// a packet is longer than max_packet_size bytes
+    TooLarge { offset: u64, limit: usize },
+    /// lists are nested deeper than max_depth (the root list is depth 1)
+    TooDeep { offset: u64, limit: usize },
+    /// the stream ended in the middle of a packet
+    UnexpectedEnd,
+}
+
+impl StreamParser {
+    pub const DEFAULT_MAX_PACKET_SIZE: usize = 1 << 20;
+    pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+    pub fn new() -> Self {
+        StreamParser {
+            max_packet_size: Self::DEFAULT_MAX_PACKET_SIZE,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            open_lists: vec![],
+            integer: None,
+            string: None,
+            packet_size: 0,
+            offset: 0,
+            ready: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// Longest packet in bytes, from its [ to its ] (1 MiB by default).
+    pub fn max_packet_size(mut self, bytes: usize) -> Self {
+        self.max_packet_size = bytes;
+        self
+    }
+
+    /// Deepest list nesting, the root list is 1 (256 by default).
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Parses chunk, packets that are complete can be taken with next_packet.
+    ///
+    /// Packets finished before an error in the same chunk can still be taken.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), StreamError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+
+        for byte in chunk {
+            if let Err(error) = self.byte(*byte) {
+                self.open_lists.clear();
+                self.integer = None;
+                self.string = None;
+                self.error = Some(error.clone());
+
+                return Err(error);
+            }
+
+            self.offset += 1;
+        }
+
+        Ok(())
+    }
+
+    /// The oldest complete packet that hasn't been taken yet.
+    pub fn next_packet(&mut self) -> Option<PacketDatum> {
+        self.ready.pop_front()
+    }
+
+    /// True while a packet has started but its root list hasn't closed.
+    pub fn in_packet(&self) -> bool {
+        !self.open_lists.is_empty()
+    }
+
+    /// Call when the stream ends: Err if it ended in the middle of a packet.
+    pub fn finish(&self) -> Result<(), StreamError> {
+        match (&self.error, self.in_packet()) {
+            (Some(error), _) => Err(error.clone()),
+            (None, true) => Err(StreamError::UnexpectedEnd),
+            (None, false) => Ok(()),
+        }
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), StreamError> {
+        if self.in_packet() {
+            self.packet_size += 1;
+
+            if self.packet_size > self.max_packet_size {
+                return Err(StreamError::TooLarge {
+                    offset: self.offset,
+                    limit: self.max_packet_size,
+                });
+            }
+        }
+
+        if let Some(string) = &mut self.string {
+            // control characters have to be escaped, no need to wait for the closing "
+            if byte < 0x20 {
+                return Err(StreamError::Parse {
+                    offset: self.offset,
+                });
+            }
+
+            string.bytes.push(byte);
+
+            match (string.escaped, byte) {
+                (false, b'"') => self.end_string()?,
+                (false, b'\\') => string.escaped = true,
+                _ => string.escaped = false,
+            }
+
+            return Ok(());
+        }
+
+        if let Some(integer) = &mut self.integer {
+            if byte.is_ascii_digit() {
+                integer.value = integer
+                    .value
+                    .checked_mul(10)
+                    .and_then(|value| value.checked_sub((byte - b'0') as i32))
+                    .ok_or(StreamError::Parse {
+                        offset: self.offset,
+                    })?;
+                integer.digits += 1;
+
+                return Ok(());
+            }
+
+            self.end_integer()?;
+        }
+
+        match byte {
+            b',' => Ok(()),
+            byte if byte.is_ascii_whitespace() => Ok(()),
+            b'[' => {
+                if self.open_lists.len() == self.max_depth {
+                    return Err(StreamError::TooDeep {
+                        offset: self.offset,
+                        limit: self.max_depth,
+                    });
+                }
+
+                if !self.in_packet() {
+                    self.packet_size = 1;
+                }
+
+                self.open_lists.push(vec![]);
+                Ok(())
+            }
+            b']' => {
+                let children = self.open_lists.pop().ok_or(StreamError::Parse {
+                    offset: self.offset,
+                })?;
+
+                self.push_item(PacketDatum::List(children));
+                Ok(())
+            }
+            // integers always live inside a list
+            b'-' | b'0'..=b'9' if self.in_packet() => {
+                self.integer = Some(PartialInteger {
+                    negative: byte == b'-',
+                    value: match byte {
+                        b'-' => 0,
+                        digit => -((digit - b'0') as i32),
+                    },
+                    digits: usize::from(byte != b'-'),
+                });
+                Ok(())
+            }
+            b'"' if self.in_packet() => {
+                self.string = Some(PartialString {
+                    start: self.offset,
+                    bytes: vec![byte],
+                    escaped: false,
+                });
+                Ok(())
+            }
+            _ => Err(StreamError::Parse {
+                offset: self.offset,
+            }),
+        }
+    }
+
+    // the byte at self.offset isn't a digit, so the integer before it is complete
+    fn end_integer(&mut self) -> Result<(), StreamError> {
+        let integer = self.integer.take().expect("an integer is being read");
+
+        let value = match (integer.digits, integer.negative) {
+            // a lone -, the error is at the byte that should have been a digit
+            (0, _) => None,
+            (_, true) => Some(integer.value),
+            (_, false) => integer.value.checked_neg(),
+        }
+        .ok_or(StreamError::Parse {
+            offset: self.offset,
+        })?;
+
+        self.push_item(PacketDatum::Integer(value));
+
+        Ok(())
+    }
+
+    // the byte at self.offset closed the string
+    fn end_string(&mut self) -> Result<(), StreamError> {
+        let string = self.string.take().expect("a string is being read");
+
+        let s = std::str::from_utf8(&string.bytes).map_err(|e| StreamError::Parse {
+            offset: string.start + e.valid_up_to() as u64,
+        })?;
+
+        // the quotes are balanced, so only the escapes can be wrong
+        match scan_string(s, 0) {
+            Ok(_) => (),
+            Err(StringError::InvalidEscape(span)) => {
+                return Err(StreamError::Parse {
+                    offset: string.start + span.start as u64,
+                })
+            }
+            Err(_) => unreachable!("closed and without control characters"),
+        }
+
+        self.push_item(PacketDatum::String(unescape(&s[1..s.len() - 1])));
+
+        Ok(())
+    }
+
+    fn push_item(&mut self, packet_datum: PacketDatum) {
+        match self.open_lists.last_mut() {
+            Some(children) => children.push(Rc::new(RefCell::new(packet_datum))),
+            None => self.ready.push_back(packet_datum),
+        }
+    }
+}
+
+impl Default for StreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Parse { offset } => write!(f, "invalid packet at byte {}", offset),
+            StreamError::TooLarge { offset, limit } => {
+                write!(f, "packet longer than {} bytes at byte {}", limit, offset)
+            }
+            StreamError::TooDeep { offset, limit } => {
+                write!(f, "lists nested deeper than {} at byte {}", limit, offset)
+            }
+            StreamError::UnexpectedEnd => write!(f, "the stream ended in the middle of a packet"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datum::tests::parse;
+
+    fn packets(parser: &mut StreamParser) -> Vec<PacketDatum> {
+        std::iter::from_fn(|| parser.next_packet()).collect()
+    }
+
+    const STREAM: &str = "[1,1,3,1,1]\n[1,1,5,1,1]\n\n[[1],[2,3,4]]\n[[1],4]\n\n[[-2147483648]] \
+        [2147483647,[]]\n[\"temp\",[\"a\\\"b\"],\"\\u00e9😀\"]";
+
+    #[test]
+    fn one_chunk() {
+        let mut parser = StreamParser::new();
+        parser.feed(STREAM.as_bytes()).unwrap();
+
+        let expected: Vec<PacketDatum> = STREAM.split_whitespace().map(parse).collect();
+
+        assert!(packets(&mut parser) == expected);
+        assert!(parser.finish().is_ok());
+    }
+
+    #[test]
+    // every split point, including in the middle of integers like 2147483647
+    fn split_anywhere() {
+        let expected: Vec<PacketDatum> = STREAM.split_whitespace().map(parse).collect();
+
+        for split in 0..=STREAM.len() {
+            let mut parser = StreamParser::new();
+            let (first, second) = STREAM.as_bytes().split_at(split);
+
+            parser.feed(first).unwrap();
+            let mut parsed = packets(&mut parser);
+            parser.feed(second).unwrap();
+            parsed.extend(packets(&mut parser));
+
+            assert!(parsed == expected);
+            assert!(parser.finish().is_ok());
+        }
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let mut parser = StreamParser::new();
+        let mut parsed = vec![];
+
+        for byte in "[12,[3".bytes() {
+            parser.feed(&[byte]).unwrap();
+            parsed.extend(packets(&mut parser));
+        }
+
+        assert!(parsed.is_empty());
+        assert!(parser.in_packet());
+        assert!(parser.finish() == Err(StreamError::UnexpectedEnd));
+
+        // the root list closes: emitted straight away, without waiting for a separator
+        parser.feed(b"4]]").unwrap();
+
+        assert!(parser.next_packet() == Some(parse("[12,[34]]")));
+        assert!(!parser.in_packet());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (stream, offset) in [
+            ("[1,a]", 3),
+            ("]", 0),
+            ("1", 0),
+            ("[1]2", 3),
+            ("[-]", 2),
+            ("[--1]", 2),
+            ("[2147483648]", 11),
+            ("[-2147483649]", 11),
+            ("[\"a\\x\"]", 3),
+            ("[\"ab\ncd\"]", 4),
+            ("[\"\\ud83d\"]", 2),
+            ("\"a\"", 0),
+        ] {
+            let mut parser = StreamParser::new();
+
+            assert!(parser.feed(stream.as_bytes()) == Err(StreamError::Parse { offset }));
+        }
+    }
+
+    #[test]
+    // packets before the error can still be taken, nothing after it is parsed
+    fn errors_stick() {
+        let mut parser = StreamParser::new();
+
+        let error = parser.feed(b"[1] [2] ] [3]");
+        assert!(error == Err(StreamError::Parse { offset: 8 }));
+        assert!(packets(&mut parser) == vec![parse("[1]"), parse("[2]")]);
+
+        assert!(parser.feed(b"[4]") == error);
+        assert!(parser.finish() == error);
+        assert!(parser.next_packet().is_none());
+    }
+
+    #[test]
+    fn size_limit() {
+        let mut parser = StreamParser::new().max_packet_size(7);
+
+        // separators between packets don't count
+        parser.feed(b"[1,2,3]   ,\n[4, 5 ]").unwrap();
+        assert!(packets(&mut parser).len() == 2);
+
+        assert!(
+            parser.feed(b"[1,2,3,4]")
+                == Err(StreamError::TooLarge {
+                    offset: 26,
+                    limit: 7
+                })
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut parser = StreamParser::new().max_depth(3);
+
+        parser.feed(b"[[[1]]]").unwrap();
+        assert!(parser.next_packet() == Some(parse("[[[1]]]")));
+
+        assert!(
+            parser.feed(b"[[[[")
+                == Err(StreamError::TooDeep {
+                    offset: 10,
+                    limit: 3
+                })
+        );
+    }
+}